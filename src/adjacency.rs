@@ -0,0 +1,313 @@
+//! Detection of walls shared between placed rooms.
+use crate::map_gen::{Direction, Room, RoomPlacement, Tile};
+use cgmath::*;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// A stretch of border where two placed rooms lie flush against each other.
+#[derive(Clone, Debug)]
+pub struct SharedEdge {
+    /// Index of the first room in the analysed slice of placements.
+    pub a: usize,
+    /// Index of the second room in the analysed slice of placements.
+    pub b: usize,
+    /// The side of `a` that touches `b`.
+    pub side: Direction,
+    /// Whether this is the edge that one of the rooms was attached through when
+    /// it was placed, or that a connector joins a loop through.
+    pub attached: bool,
+    /// First and one-past-last coordinate of the edge, along the axis the edge
+    /// runs on.
+    pub span: (i32, i32),
+    /// Every position along the edge where at least one of the two rooms has
+    /// an open border tile.
+    pub tiles: Vec<EdgeTile>,
+}
+
+/// A pair of facing border tiles along a shared edge.
+#[derive(Copy, Clone, Debug)]
+pub struct EdgeTile {
+    /// Map position of the border tile belonging to `a`.
+    pub a_pos: Point2<i32>,
+    /// Map position of the border tile belonging to `b`.
+    pub b_pos: Point2<i32>,
+    pub a_open: bool,
+    pub b_open: bool,
+}
+
+impl EdgeTile {
+    /// Both entrances line up, so the rooms are connected here.
+    pub fn is_door(&self) -> bool {
+        self.a_open && self.b_open
+    }
+
+    /// Only one side is open, so the entrance runs straight into a wall.
+    pub fn is_blocked(&self) -> bool {
+        self.a_open != self.b_open
+    }
+}
+
+impl SharedEdge {
+    /// Aligned entrance pairs along the edge.
+    pub fn doors(&self) -> impl Iterator<Item = &EdgeTile> {
+        self.tiles.iter().filter(|t| t.is_door())
+    }
+
+    /// Entrances along the edge that face a solid tile.
+    pub fn blocked(&self) -> impl Iterator<Item = &EdgeTile> {
+        self.tiles.iter().filter(|t| t.is_blocked())
+    }
+}
+
+/// What to do with the entrances along edges that rooms were not attached
+/// through.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DoorPolicy {
+    /// Carve through the wall opposite every blocked entrance, turning it into a
+    /// door and creating a loop in the map.
+    Open,
+    /// Fill in every accidental door so rooms only connect through the edge they
+    /// were attached through.
+    Seal,
+}
+
+impl FromStr for DoorPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(Self::Open),
+            "seal" => Ok(Self::Seal),
+            _ => Err(format!(
+                "unknown door policy `{}`, expected open or seal",
+                s
+            )),
+        }
+    }
+}
+
+/// Finds every edge shared between two rooms in `placements`.
+pub fn shared_edges(placements: &[RoomPlacement]) -> Vec<SharedEdge> {
+    // Rooms can only touch if the max of one is equal to the min of the other
+    // along some axis, so bucket the rooms by their min coordinates and look up
+    // each room's max coordinates.
+    let mut by_min_x: HashMap<i32, Vec<usize>> = HashMap::new();
+    let mut by_min_y: HashMap<i32, Vec<usize>> = HashMap::new();
+    for (i, placement) in placements.iter().enumerate() {
+        let r = placement.rect();
+        by_min_x.entry(r.min.x).or_default().push(i);
+        by_min_y.entry(r.min.y).or_default().push(i);
+    }
+
+    let mut edges = Vec::new();
+    for (a, placement) in placements.iter().enumerate() {
+        let ra = placement.rect();
+        for &b in by_min_x.get(&ra.max.x).into_iter().flatten() {
+            let rb = placements[b].rect();
+            let span = (ra.min.y.max(rb.min.y), ra.max.y.min(rb.max.y));
+            if span.0 < span.1 {
+                edges.push(edge(placements, a, b, Direction::East, span));
+            }
+        }
+        for &b in by_min_y.get(&ra.max.y).into_iter().flatten() {
+            let rb = placements[b].rect();
+            let span = (ra.min.x.max(rb.min.x), ra.max.x.min(rb.max.x));
+            if span.0 < span.1 {
+                edges.push(edge(placements, a, b, Direction::South, span));
+            }
+        }
+    }
+    edges
+}
+
+fn edge(
+    placements: &[RoomPlacement],
+    a: usize,
+    b: usize,
+    side: Direction,
+    span: (i32, i32),
+) -> SharedEdge {
+    let (pa, pb) = (&placements[a], &placements[b]);
+    let (ra, rb) = (pa.rect(), pb.rect());
+    let tiles = (span.0..span.1)
+        .map(|i| match side {
+            Direction::East => (Point2::new(ra.max.x - 1, i), Point2::new(rb.min.x, i)),
+            Direction::South => (Point2::new(i, ra.max.y - 1), Point2::new(i, rb.min.y)),
+            _ => unreachable!(),
+        })
+        .map(|(a_pos, b_pos)| EdgeTile {
+            a_pos,
+            b_pos,
+            a_open: pa.tile_at(a_pos).is_empty(),
+            b_open: pb.tile_at(b_pos).is_empty(),
+        })
+        .filter(|t| t.a_open || t.b_open)
+        .collect();
+    SharedEdge {
+        a,
+        b,
+        side,
+        attached: [pa.parent, pa.joins].contains(&Some(pb.id))
+            || [pb.parent, pb.joins].contains(&Some(pa.id)),
+        span,
+        tiles,
+    }
+}
+
+/// A single tile of a placed room that should be replaced.
+#[derive(Copy, Clone, Debug)]
+pub struct TileChange {
+    /// Index of the room the tile belongs to.
+    pub room: usize,
+    pub pos: Point2<i32>,
+    pub tile: Tile,
+}
+
+/// Computes the tile changes needed to apply `policy` to the given edges. Edges
+/// that rooms were attached through are left untouched.
+pub fn apply_policy(edges: &[SharedEdge], policy: DoorPolicy) -> Vec<TileChange> {
+    let mut changes = Vec::new();
+    for edge in edges.iter().filter(|e| !e.attached) {
+        match policy {
+            DoorPolicy::Open => {
+                for t in edge.blocked() {
+                    let (room, pos) = if t.a_open {
+                        (edge.b, t.b_pos)
+                    } else {
+                        (edge.a, t.a_pos)
+                    };
+                    changes.push(TileChange {
+                        room,
+                        pos,
+                        tile: Tile::Empty,
+                    });
+                }
+            }
+            DoorPolicy::Seal => {
+                for t in edge.doors() {
+                    for &(room, pos) in &[(edge.a, t.a_pos), (edge.b, t.b_pos)] {
+                        changes.push(TileChange {
+                            room,
+                            pos,
+                            tile: Tile::Dirt,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    changes
+}
+
+/// Changes the tiles of the rooms in `placements`, updating their entrances to
+/// match. Sockets that are still there keep their types. Returns the indices
+/// of the rooms that changed, in ascending order.
+pub fn apply_changes(placements: &mut [RoomPlacement], changes: &[TileChange]) -> Vec<usize> {
+    let mut changed = Vec::new();
+    for change in changes {
+        let placement = &mut placements[change.room];
+        let local = change.pos - placement.pos;
+        placement.room.layout[local.y as usize][local.x as usize] = change.tile;
+        changed.push(change.room);
+    }
+    changed.sort_unstable();
+    changed.dedup();
+    for &i in &changed {
+        let room = &mut placements[i].room;
+        let mut rebuilt = Room::from_layout(room.name.clone(), room.layout.clone());
+        rebuilt.copy_socket_kinds(room);
+        *room = rebuilt;
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_gen::{MapGenerator, Tile::*};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn accidental_edges() {
+        let corridor = Room::new(
+            "corridor",
+            &[
+                &[Dirt, Dirt, Dirt],
+                &[Empty, Empty, Empty],
+                &[Dirt, Dirt, Dirt],
+            ],
+        );
+        let shaft = Room::new(
+            "shaft",
+            &[
+                &[Dirt, Empty, Dirt],
+                &[Dirt, Empty, Dirt],
+                &[Dirt, Empty, Dirt],
+            ],
+        );
+        // `b` is attached to the east of `a`, and `c` happens to sit below `b`
+        // without being attached to it.
+        let placements = vec![
+            corridor.place(Point2::new(0, 0), 0, None),
+            corridor.place(Point2::new(3, 0), 1, Some(0)),
+            shaft.place(Point2::new(3, 3), 2, Some(0)),
+        ];
+        let edges = shared_edges(&placements);
+        assert_eq!(edges.len(), 2);
+
+        let attached = edges.iter().find(|e| e.attached).unwrap();
+        assert_eq!((attached.a, attached.b), (0, 1));
+        assert_eq!(attached.side, Direction::East);
+        assert_eq!(attached.doors().count(), 1);
+
+        let accidental = edges.iter().find(|e| !e.attached).unwrap();
+        assert_eq!((accidental.a, accidental.b), (1, 2));
+        assert_eq!(accidental.side, Direction::South);
+        assert_eq!(accidental.span, (3, 6));
+        assert_eq!(accidental.blocked().count(), 1);
+
+        let open = apply_policy(&edges, DoorPolicy::Open);
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].room, 1);
+        assert_eq!(open[0].pos, Point2::new(4, 2));
+        assert!(apply_policy(&edges, DoorPolicy::Seal).is_empty());
+
+        let mut opened = placements.clone();
+        assert_eq!(apply_changes(&mut opened, &open), vec![1]);
+        assert_eq!(opened[1].tile_at(Point2::new(4, 2)), Empty);
+        assert_eq!(opened[1].room.entrances[Direction::South as usize], vec![1]);
+        let edges = shared_edges(&opened);
+        assert_eq!(
+            edges.iter().find(|e| !e.attached).unwrap().doors().count(),
+            1
+        );
+        assert!(apply_policy(&edges, DoorPolicy::Open).is_empty());
+    }
+
+    #[test]
+    fn generated_maps_are_sealed() {
+        let accidental_doors = |placed: &[RoomPlacement]| {
+            shared_edges(placed)
+                .iter()
+                .filter(|e| !e.attached)
+                .map(|e| e.doors().count())
+                .sum::<usize>()
+        };
+        let unsealed: Vec<_> = MapGenerator::new(64, 64, StdRng::seed_from_u64(2)).collect();
+        assert!(accidental_doors(&unsealed) > 0);
+        let mut map_gen =
+            MapGenerator::new(64, 64, StdRng::seed_from_u64(2)).with_doors(DoorPolicy::Seal);
+        let returned: Vec<RoomPlacement> = map_gen.by_ref().collect();
+        let placed = map_gen.placements();
+        assert_eq!(accidental_doors(placed), 0);
+        // Rooms that changed are returned again, like connectors.
+        let mut latest = HashMap::new();
+        for placement in returned {
+            latest.insert(placement.id, placement);
+        }
+        for placement in placed {
+            assert_eq!(latest[&placement.id].room.layout, placement.room.layout);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Generating maps for many seeds at once and comparing their statistics.
+use crate::map_gen::MapGenerator;
+use crate::stats::MapStats;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+/// Extracts a single number from a map's statistics.
+pub type Metric = fn(&MapStats) -> f64;
+
+/// Statistics that are compared across maps, by name.
+pub const METRICS: [(&str, Metric); 7] = [
+    ("rooms", |s| s.rooms as f64),
+    ("fill_ratio", |s| s.fill_ratio),
+    ("empty_dirt_ratio", |s| s.empty_dirt_ratio),
+    ("dead_ends", |s| s.dead_ends as f64),
+    ("longest_path", |s| s.longest_path as f64),
+    ("unattached_entrances", |s| s.unattached_entrances as f64),
+    ("generation_secs", |s| s.generation_secs),
+];
+
+/// Statistics of the map generated from a single seed.
+#[derive(Clone, Debug)]
+pub struct SeedStats {
+    pub seed: u64,
+    pub stats: MapStats,
+    /// Names of the metrics for which this map is an outlier.
+    pub outliers: Vec<&'static str>,
+}
+
+/// Distribution of a metric over every generated map.
+#[derive(Copy, Clone, Debug)]
+pub struct Summary {
+    pub min: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+/// Generates a map for every seed in `seeds`, spread across `threads` threads,
+/// and flags the maps that are outliers. Results are sorted by seed.
+pub fn generate<F>(seeds: Range<u64>, threads: usize, new_generator: F) -> Vec<SeedStats>
+where
+    F: Fn(StdRng) -> MapGenerator<StdRng> + Sync,
+{
+    // Counts up from zero rather than from the first seed, so that it cannot
+    // wrap around past the last one.
+    let next = AtomicU64::new(0);
+    let count = seeds.end.saturating_sub(seeds.start);
+    let results = Mutex::new(Vec::new());
+    thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= count {
+                    break;
+                }
+                let seed = seeds.start + i;
+                let start = Instant::now();
+                let mut map_gen = new_generator(StdRng::seed_from_u64(seed));
+                map_gen.by_ref().for_each(drop);
+                let stats = MapStats::compute(map_gen.placements(), start.elapsed());
+                results.lock().unwrap().push(SeedStats {
+                    seed,
+                    stats,
+                    outliers: Vec::new(),
+                });
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|r| r.seed);
+    flag_outliers(&mut results);
+    results
+}
+
+/// Flags every map whose value for a metric lies outside of Tukey's fences,
+/// more than one and a half interquartile ranges beyond the first or third
+/// quartile.
+fn flag_outliers(results: &mut [SeedStats]) {
+    for (name, metric) in METRICS.iter() {
+        let values = sorted(results, *metric);
+        if values.is_empty() {
+            continue;
+        }
+        let (q1, q3) = (percentile(&values, 25.0), percentile(&values, 75.0));
+        let iqr = q3 - q1;
+        let (low, high) = (q1 - 1.5 * iqr, q3 + 1.5 * iqr);
+        for result in results.iter_mut() {
+            let value = metric(&result.stats);
+            if value < low || value > high {
+                result.outliers.push(name);
+            }
+        }
+    }
+}
+
+/// Summarizes the distribution of every metric, leaving out values that are
+/// not finite.
+pub fn summarize(results: &[SeedStats]) -> Vec<(&'static str, Summary)> {
+    METRICS
+        .iter()
+        .map(|(name, metric)| {
+            let values = sorted(results, *metric);
+            let summary = Summary {
+                min: values.first().copied().unwrap_or(0.0),
+                p50: percentile(&values, 50.0),
+                p90: percentile(&values, 90.0),
+                p99: percentile(&values, 99.0),
+                max: values.last().copied().unwrap_or(0.0),
+                mean: values.iter().sum::<f64>() / values.len().max(1) as f64,
+            };
+            (*name, summary)
+        })
+        .collect()
+}
+
+/// Writes one line per seed with the value of every metric and the metrics for
+/// which the map is an outlier, separated by semicolons.
+pub fn write_csv<W: Write>(results: &[SeedStats], mut out: W) -> io::Result<()> {
+    write!(out, "seed")?;
+    for (name, _) in METRICS.iter() {
+        write!(out, ",{}", name)?;
+    }
+    writeln!(out, ",outliers")?;
+    for result in results {
+        write!(out, "{}", result.seed)?;
+        for (_, metric) in METRICS.iter() {
+            write!(out, ",{}", metric(&result.stats))?;
+        }
+        writeln!(out, ",{}", result.outliers.join(";"))?;
+    }
+    Ok(())
+}
+
+/// Every finite value of the metric, in ascending order.
+fn sorted(results: &[SeedStats], metric: Metric) -> Vec<f64> {
+    let mut values: Vec<f64> = results
+        .iter()
+        .map(|r| metric(&r.stats))
+        .filter(|v| v.is_finite())
+        .collect();
+    values.sort_by(f64::total_cmp);
+    values
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn nearest_rank() {
+        let values: Vec<f64> = (1..=10).map(f64::from).collect();
+        assert_eq!(percentile(&values, 50.0), 5.0);
+        assert_eq!(percentile(&values, 90.0), 9.0);
+        assert_eq!(percentile(&values, 99.0), 10.0);
+        assert_eq!(percentile(&values, 0.0), 1.0);
+    }
+
+    #[test]
+    fn non_finite_values_are_left_out() {
+        let results: Vec<SeedStats> = [1.0, f64::NAN, 0.5, f64::INFINITY]
+            .iter()
+            .enumerate()
+            .map(|(seed, ratio)| {
+                let mut stats = MapStats::compute(&[], Duration::from_secs(1));
+                stats.fill_ratio = *ratio;
+                SeedStats {
+                    seed: seed as u64,
+                    stats,
+                    outliers: Vec::new(),
+                }
+            })
+            .collect();
+        let fill_ratio = summarize(&results)
+            .into_iter()
+            .find(|(name, _)| *name == "fill_ratio")
+            .unwrap()
+            .1;
+        assert_eq!((fill_ratio.min, fill_ratio.max), (0.5, 1.0));
+        assert_eq!(fill_ratio.mean, 0.75);
+    }
+
+    #[test]
+    fn outliers_after_an_empty_metric() {
+        let mut results: Vec<SeedStats> = (0..8)
+            .map(|seed| {
+                let mut stats = MapStats::compute(&[], Duration::from_secs(1));
+                stats.fill_ratio = f64::NAN;
+                SeedStats {
+                    seed,
+                    stats,
+                    outliers: Vec::new(),
+                }
+            })
+            .collect();
+        results[3].stats.generation_secs = 100.0;
+        flag_outliers(&mut results);
+        assert_eq!(results[3].outliers, vec!["generation_secs"]);
+        assert!(results
+            .iter()
+            .filter(|r| r.seed != 3)
+            .all(|r| r.outliers.is_empty()));
+    }
+
+    #[test]
+    fn seeds_up_to_the_largest() {
+        let results = generate(u64::MAX - 2..u64::MAX, 4, |rng| {
+            MapGenerator::new(32, 32, rng)
+        });
+        assert_eq!(
+            results.iter().map(|r| r.seed).collect::<Vec<_>>(),
+            vec![u64::MAX - 2, u64::MAX - 1]
+        );
+    }
+
+    #[test]
+    fn deterministic_seeds() {
+        let results = generate(0..4, 2, |rng| MapGenerator::new(64, 64, rng));
+        let again = generate(2..4, 1, |rng| MapGenerator::new(64, 64, rng));
+        assert_eq!(
+            results.iter().map(|r| r.seed).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+        for (a, b) in results[2..].iter().zip(&again) {
+            assert_eq!(a.stats.rooms, b.stats.rooms);
+            assert_eq!(a.stats.longest_path, b.stats.longest_path);
+        }
+    }
+}
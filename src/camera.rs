@@ -0,0 +1,120 @@
+//! Mapping between map tiles and window pixels.
+use crate::rect::Rect;
+use cgmath::*;
+
+/// The part of the map visible in the window.
+#[derive(Copy, Clone, Debug)]
+pub struct Camera {
+    /// Map position shown in the top left corner of the window.
+    pub pos: Point2<f64>,
+    /// Size of a tile in pixels.
+    pub zoom: f64,
+}
+
+impl Camera {
+    /// Smallest and largest tile size in pixels.
+    const ZOOM_RANGE: (f64, f64) = (0.125, 64.0);
+
+    /// Camera in the top left corner of the map. The tile size is kept within
+    /// the zoom range.
+    pub fn new(tile_size: f64) -> Self {
+        Self {
+            pos: Point2::new(0.0, 0.0),
+            zoom: tile_size.max(Self::ZOOM_RANGE.0).min(Self::ZOOM_RANGE.1),
+        }
+    }
+
+    /// Converts a map position to a window position.
+    pub fn to_window(&self, pos: Point2<f64>) -> Point2<f64> {
+        Point2::from_vec((pos - self.pos) * self.zoom)
+    }
+
+    /// Converts a window position to a map position.
+    pub fn to_map(&self, pos: Point2<f64>) -> Point2<f64> {
+        self.pos + pos.to_vec() / self.zoom
+    }
+
+    /// Moves the map by the given number of pixels.
+    pub fn pan(&mut self, pixels: Vector2<f64>) {
+        self.pos -= pixels / self.zoom;
+    }
+
+    /// Multiplies the zoom by `factor`, keeping the map position under the
+    /// window position `anchor` in place.
+    pub fn zoom_at(&mut self, anchor: Point2<f64>, factor: f64) {
+        let fixed = self.to_map(anchor);
+        self.zoom = (self.zoom * factor)
+            .max(Self::ZOOM_RANGE.0)
+            .min(Self::ZOOM_RANGE.1);
+        self.pos = fixed - anchor.to_vec() / self.zoom;
+    }
+
+    /// Keeps the map position at the center of the window in place when the
+    /// window is resized.
+    pub fn resize(&mut self, old_size: (u32, u32), new_size: (u32, u32)) {
+        let delta = Vector2::new(
+            new_size.0 as f64 - old_size.0 as f64,
+            new_size.1 as f64 - old_size.1 as f64,
+        );
+        self.pan(delta / 2.0);
+    }
+
+    /// Zooms and pans so that `bounds` fills as much of the window as possible
+    /// while staying entirely visible.
+    pub fn fit(&mut self, bounds: &Rect, window_size: (u32, u32)) {
+        let size = bounds.max - bounds.min;
+        self.zoom = (window_size.0 as f64 / size.x.max(1) as f64)
+            .min(window_size.1 as f64 / size.y.max(1) as f64)
+            .max(Self::ZOOM_RANGE.0)
+            .min(Self::ZOOM_RANGE.1);
+        let center = Point2::new(
+            (bounds.min.x + bounds.max.x) as f64 / 2.0,
+            (bounds.min.y + bounds.max.y) as f64 / 2.0,
+        );
+        let half_window = Vector2::new(window_size.0 as f64, window_size.1 as f64) / 2.0;
+        self.pos = center - half_window / self.zoom;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zoom_keeps_anchor() {
+        let mut camera = Camera::new(2.0);
+        camera.pan(Vector2::new(-10.0, 4.0));
+        let anchor = Point2::new(100.0, 50.0);
+        let before = camera.to_map(anchor);
+        camera.zoom_at(anchor, 1.5);
+        assert_eq!(camera.zoom, 3.0);
+        let after = camera.to_map(anchor);
+        assert!((before - after).magnitude() < 1e-9);
+        assert!((camera.to_window(after) - anchor).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn new_clamps_tile_size() {
+        assert_eq!(Camera::new(0.0).zoom, Camera::ZOOM_RANGE.0);
+        assert_eq!(Camera::new(128.0).zoom, Camera::ZOOM_RANGE.1);
+    }
+
+    #[test]
+    fn fit_bounds() {
+        let mut camera = Camera::new(1.0);
+        let bounds = Rect {
+            min: Point2::new(-10, 0),
+            max: Point2::new(90, 50),
+        };
+        camera.fit(&bounds, (400, 400));
+        assert_eq!(camera.zoom, 4.0);
+        assert_eq!(
+            camera.to_window(Point2::new(-10.0, 0.0)),
+            Point2::new(0.0, 100.0)
+        );
+        assert_eq!(
+            camera.to_window(Point2::new(90.0, 50.0)),
+            Point2::new(400.0, 300.0)
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! Saving what the viewer shows as images.
+#[cfg(feature = "sdl")]
+use sdl2::{pixels::PixelFormatEnum, render::Canvas, video::Window};
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// Centiseconds each frame of a GIF is shown for, as close to the viewer's
+/// 60 frames per second as GIFs allow.
+const GIF_FRAME_DELAY: u16 = 2;
+
+/// The pixels of a frame, three bytes per pixel, row by row.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Vec<u8>,
+}
+
+impl Image {
+    /// Reads back whatever has been drawn to `canvas` since it was last
+    /// presented.
+    #[cfg(feature = "sdl")]
+    pub fn from_canvas(canvas: &Canvas<Window>) -> Self {
+        let (width, height) = canvas.output_size().unwrap();
+        let rgb = canvas.read_pixels(None, PixelFormatEnum::RGB24).unwrap();
+        Self { width, height, rgb }
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(File::create(path)?), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.rgb))
+            .map_err(io::Error::other)
+    }
+}
+
+/// Saves every frame it is given, either as an animated GIF or as a numbered
+/// sequence of PNGs in a directory.
+pub enum Recorder {
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+        width: u32,
+        height: u32,
+    },
+    Png {
+        dir: PathBuf,
+        frames: usize,
+    },
+}
+
+impl Recorder {
+    /// Records to `path`, as a GIF if its extension is `gif` and otherwise into
+    /// the directory at `path`, which is created if needed. Every frame of a GIF
+    /// has to be `width` by `height` pixels.
+    pub fn new(path: &Path, width: u32, height: u32) -> io::Result<Self> {
+        if path.extension().is_some_and(|e| e == "gif") {
+            let file = BufWriter::new(File::create(path)?);
+            let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &[])
+                .map_err(io::Error::other)?;
+            encoder
+                .set_repeat(gif::Repeat::Infinite)
+                .map_err(io::Error::other)?;
+            Ok(Self::Gif {
+                encoder,
+                width,
+                height,
+            })
+        } else {
+            fs::create_dir_all(path)?;
+            Ok(Self::Png {
+                dir: path.to_owned(),
+                frames: 0,
+            })
+        }
+    }
+
+    pub fn record(&mut self, image: &Image) -> io::Result<()> {
+        match self {
+            Self::Gif {
+                encoder,
+                width,
+                height,
+            } => {
+                if (image.width, image.height) != (*width, *height) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "the window changed size while recording a GIF",
+                    ));
+                }
+                let mut frame = gif::Frame::from_rgb_speed(
+                    image.width as u16,
+                    image.height as u16,
+                    &image.rgb,
+                    10,
+                );
+                frame.delay = GIF_FRAME_DELAY;
+                encoder.write_frame(&frame).map_err(io::Error::other)
+            }
+            Self::Png { dir, frames } => {
+                *frames += 1;
+                image.save_png(&dir.join(format!("frame-{:05}.png", frames)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_sequence() {
+        let dir = std::env::temp_dir().join(format!("capture-{}", std::process::id()));
+        let image = Image {
+            width: 2,
+            height: 1,
+            rgb: vec![255, 0, 0, 0, 0, 255],
+        };
+        let mut recorder = Recorder::new(&dir, 2, 1).unwrap();
+        recorder.record(&image).unwrap();
+        recorder.record(&image).unwrap();
+
+        let decoder = png::Decoder::new(File::open(dir.join("frame-00002.png")).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut rgb = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut rgb).unwrap();
+        assert_eq!(rgb, image.rgb);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
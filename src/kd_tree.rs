@@ -2,7 +2,7 @@
 use crate::rect::Rect;
 
 /// Simple KD-tree for determining if rectangles overlap during map generation.
-/// Adding rectangles is irreversible.
+/// Removed rectangles are only marked as such, so the tree never shrinks.
 ///
 /// This data structure does not automatically balance itself, and thus its
 /// efficiency depends on the rectangles to be spatially balanced by being unable to
@@ -10,26 +10,49 @@ use crate::rect::Rect;
 ///
 /// This invariant is not upheld by any of the functions to insert rectangles; it must
 /// be upheld by the caller.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub enum KDTree {
+    #[default]
     Empty,
-    Populated { root: TreeNode },
+    Populated {
+        root: TreeNode,
+        /// Largest padding of any rectangle in the tree.
+        max_padding: i32,
+    },
 }
 
 impl KDTree {
-    /// Add a rectangle to the KDTree. This cannot be undone.
+    /// Add a rectangle to the KDTree.
     pub fn add_rect(&mut self, r: Rect) {
+        self.add_padded_rect(r, 0);
+    }
+
+    /// Add a rectangle that `too_close` keeps an extra `padding` tiles away
+    /// from.
+    pub fn add_padded_rect(&mut self, r: Rect, padding: i32) {
         match self {
             Self::Empty => {
                 *self = KDTree::Populated {
-                    root: TreeNode {
-                        left: None,
-                        right: None,
-                        rect: r,
-                    },
+                    root: TreeNode::new(r, padding),
+                    max_padding: padding,
                 }
             }
-            Self::Populated { ref mut root } => root.add_rect(r, 0_usize),
+            Self::Populated {
+                ref mut root,
+                ref mut max_padding,
+            } => {
+                *max_padding = (*max_padding).max(padding);
+                root.add_rect(r, padding, 0_usize)
+            }
+        }
+    }
+
+    /// Takes a rectangle added before out of the tree again. Returns false if
+    /// there was no such rectangle.
+    pub fn remove_rect(&mut self, r: &Rect) -> bool {
+        match self {
+            Self::Empty => false,
+            Self::Populated { ref mut root, .. } => root.remove_rect(r, 0_usize),
         }
     }
 
@@ -37,14 +60,25 @@ impl KDTree {
     pub fn overlaps(&self, r: &Rect) -> bool {
         match self {
             Self::Empty => false,
-            Self::Populated { ref root } => root.overlaps(r, 0_usize),
+            Self::Populated { ref root, .. } => {
+                root.any(r, 0_usize, &|node: &TreeNode| node.rect.overlaps(r))
+            }
         }
     }
-}
 
-impl Default for KDTree {
-    fn default() -> Self {
-        Self::Empty
+    /// Determine if any rectangle in the tree other than `except` has fewer
+    /// than `gap` tiles plus its own padding between itself and `r`.
+    pub fn too_close(&self, r: &Rect, gap: i32, except: &Rect) -> bool {
+        match self {
+            Self::Empty => false,
+            Self::Populated {
+                ref root,
+                max_padding,
+            } => root.any(&r.padded(gap + max_padding), 0_usize, &|node: &TreeNode| {
+                (node.rect.min != except.min || node.rect.max != except.max)
+                    && node.rect.padded(gap + node.padding).overlaps(r)
+            }),
+        }
     }
 }
 
@@ -53,36 +87,57 @@ pub struct TreeNode {
     left: Option<Box<TreeNode>>,
     right: Option<Box<TreeNode>>,
     rect: Rect,
+    padding: i32,
+    /// Whether the rectangle has been taken out again. The node is kept for
+    /// its children.
+    removed: bool,
 }
 
 impl TreeNode {
-    fn new(rect: Rect) -> Self {
+    fn new(rect: Rect, padding: i32) -> Self {
         Self {
             left: None,
             right: None,
             rect,
+            padding,
+            removed: false,
         }
     }
 
-    fn add_rect(&mut self, r: Rect, curr_dim: usize) {
+    fn add_rect(&mut self, r: Rect, padding: i32, curr_dim: usize) {
         let next_dim = (curr_dim + 1) % 4;
-        if r.is_dim_less(&self.rect, curr_dim) {
-            if self.left.is_none() {
-                self.left = Some(Box::new(TreeNode::new(r)));
-            } else {
-                self.left.as_mut().map(|l| l.add_rect(r, next_dim));
-            }
+        let child = if r.is_dim_less(&self.rect, curr_dim) {
+            &mut self.left
         } else {
-            if self.right.is_none() {
-                self.right = Some(Box::new(TreeNode::new(r)));
-            } else {
-                self.right.as_mut().map(|n| n.add_rect(r, next_dim));
-            }
+            &mut self.right
+        };
+        match child {
+            Some(node) => node.add_rect(r, padding, next_dim),
+            None => *child = Some(Box::new(TreeNode::new(r, padding))),
         }
     }
 
-    fn overlaps(&self, rect: &Rect, curr_dim: usize) -> bool {
-        if self.rect.overlaps(rect) {
+    /// Marks the first node holding `r` as removed. Equal rectangles take the
+    /// same path down the tree when they are added, so only that path is searched.
+    fn remove_rect(&mut self, r: &Rect, curr_dim: usize) -> bool {
+        if !self.removed && self.rect.min == r.min && self.rect.max == r.max {
+            self.removed = true;
+            return true;
+        }
+        let child = if r.is_dim_less(&self.rect, curr_dim) {
+            &mut self.left
+        } else {
+            &mut self.right
+        };
+        child
+            .as_mut()
+            .is_some_and(|node| node.remove_rect(r, (curr_dim + 1) % 4))
+    }
+
+    /// Determines if `hit` holds for any node. Only nodes whose rectangle
+    /// overlaps `bounds` are visited, so `hit` must not hold for any other.
+    fn any(&self, bounds: &Rect, curr_dim: usize, hit: &dyn Fn(&TreeNode) -> bool) -> bool {
+        if !self.removed && self.rect.overlaps(bounds) && hit(self) {
             return true;
         }
         let next_dim = (curr_dim + 1) % 4;
@@ -91,15 +146,15 @@ impl TreeNode {
                 if self
                     .left
                     .as_ref()
-                    .map_or(false, |l| l.overlaps(rect, next_dim))
+                    .is_some_and(|l| l.any(bounds, next_dim, hit))
                 {
                     true
-                } else if rect.max[i] < self.rect.min[i] {
+                } else if bounds.max[i] < self.rect.min[i] {
                     false
                 } else {
                     self.right
                         .as_ref()
-                        .map_or(false, |r| r.overlaps(rect, next_dim))
+                        .is_some_and(|r| r.any(bounds, next_dim, hit))
                 }
             }
             i @ 2..=3 => {
@@ -107,15 +162,15 @@ impl TreeNode {
                 if self
                     .right
                     .as_ref()
-                    .map_or(false, |r| r.overlaps(rect, next_dim))
+                    .is_some_and(|r| r.any(bounds, next_dim, hit))
                 {
                     true
-                } else if rect.min[i] > self.rect.max[i] {
+                } else if bounds.min[i] > self.rect.max[i] {
                     false
                 } else {
                     self.left
                         .as_ref()
-                        .map_or(false, |l| l.overlaps(rect, next_dim))
+                        .is_some_and(|l| l.any(bounds, next_dim, hit))
                 }
             }
             _ => unreachable!(),
@@ -126,39 +181,97 @@ impl TreeNode {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use cgmath::Point2;
 
     #[test]
     fn tree_overlap() {
         let r1 = Rect {
-            min: point!(0, 0),
-            max: point!(10, 10),
+            min: Point2::new(0, 0),
+            max: Point2::new(10, 10),
         };
         let r2 = Rect {
-            min: point!(10, 10),
-            max: point!(20, 20),
+            min: Point2::new(10, 10),
+            max: Point2::new(20, 20),
         };
         let r3 = Rect {
-            min: point!(20, 20),
-            max: point!(30, 30),
+            min: Point2::new(20, 20),
+            max: Point2::new(30, 30),
         };
         let mut tree = KDTree::default();
         tree.add_rect(r1);
         tree.add_rect(r2);
         tree.add_rect(r3);
         let r1 = Rect {
-            min: point!(5, 5),
-            max: point!(10, 10),
+            min: Point2::new(5, 5),
+            max: Point2::new(10, 10),
         };
         let r2 = Rect {
-            min: point!(30, 30),
-            max: point!(40, 40),
+            min: Point2::new(30, 30),
+            max: Point2::new(40, 40),
         };
         let r3 = Rect {
-            min: point!(25, 25),
-            max: point!(40, 40),
+            min: Point2::new(25, 25),
+            max: Point2::new(40, 40),
         };
         assert!(tree.overlaps(&r1));
         assert!(!tree.overlaps(&r2));
         assert!(tree.overlaps(&r3));
     }
+
+    #[test]
+    fn tree_too_close() {
+        let room = Rect {
+            min: Point2::new(0, 0),
+            max: Point2::new(10, 10),
+        };
+        let mut tree = KDTree::default();
+        tree.add_rect(room);
+        // Two tiles of rock between them.
+        let r = Rect {
+            min: Point2::new(12, 0),
+            max: Point2::new(20, 10),
+        };
+        assert!(!tree.overlaps(&r));
+        assert!(!tree.too_close(&r, 2, &r));
+        assert!(tree.too_close(&r, 3, &r));
+        assert!(!tree.too_close(&r, 3, &room));
+        tree.add_padded_rect(
+            Rect {
+                min: Point2::new(0, 30),
+                max: Point2::new(10, 40),
+            },
+            4,
+        );
+        // Padding adds to the gap.
+        let r = Rect {
+            min: Point2::new(0, 20),
+            max: Point2::new(10, 26),
+        };
+        assert!(!tree.too_close(&r, 0, &r));
+        assert!(tree.too_close(&r, 1, &r));
+    }
+
+    #[test]
+    fn tree_remove() {
+        let rects: Vec<Rect> = (0..8)
+            .map(|i| Rect {
+                min: Point2::new(i * 10, (i % 3) * 10),
+                max: Point2::new(i * 10 + 10, (i % 3) * 10 + 10),
+            })
+            .collect();
+        let mut tree = KDTree::default();
+        for r in &rects {
+            tree.add_rect(*r);
+        }
+        assert!(tree.remove_rect(&rects[4]));
+        assert!(!tree.remove_rect(&rects[4]));
+        assert!(!tree.overlaps(&rects[4]));
+        // Nodes below the removed one are still found.
+        assert!(rects
+            .iter()
+            .enumerate()
+            .all(|(i, r)| i == 4 || tree.overlaps(r)));
+        tree.add_rect(rects[4]);
+        assert!(tree.overlaps(&rects[4]));
+    }
 }
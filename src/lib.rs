@@ -0,0 +1,21 @@
+//! Random tile-based dungeon map generation.
+pub mod adjacency;
+pub mod batch;
+pub mod camera;
+pub mod capture;
+pub mod kd_tree;
+pub mod map_gen;
+pub mod mask;
+pub mod observer;
+#[cfg(feature = "sdl")]
+pub mod overlay;
+pub mod reachability;
+pub mod rect;
+#[cfg(feature = "sdl")]
+pub mod render;
+pub mod scene;
+pub mod stats;
+pub mod terminal;
+pub mod text_map;
+pub mod theme;
+pub mod world;
@@ -1,24 +1,102 @@
-mod kd_tree;
-mod map_gen;
-mod rect;
-
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use std::time::Duration;
-use structopt::StructOpt;
+use cgmath::{Point2, Vector2};
+use dungeon_explorer::{
+    adjacency, batch,
+    camera::Camera,
+    map_gen,
+    mask::Mask,
+    observer::Log,
+    reachability::Movement,
+    rect::Rect,
+    scene::Scene,
+    stats::MapStats,
+    terminal::{self, Charset},
+    text_map,
+    theme::{ColorMode, Theme},
+};
+#[cfg(feature = "sdl")]
+use dungeon_explorer::{
+    capture::{Image, Recorder},
+    overlay::SearchOverlay,
+    render::Renderer,
+};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+#[cfg(feature = "sdl")]
+use sdl2::{
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use structopt::{clap, StructOpt};
 
 #[derive(Debug, StructOpt)]
-#[structopt(name = "dungeon-explorer", about = "A nice random cavern screensaver")]
+#[structopt(
+    name = "dungeon-explorer",
+    about = "A nice random cavern screensaver",
+    after_help = "CONTROLS:
+    Space         Pause or resume
+    .             Pause and place one room
+    + / -         Place rooms faster or slower
+    N             Regenerate with a new seed
+    R             Regenerate with the same seed
+    Arrow keys    Pan
+    Mouse drag    Pan
+    Mouse wheel   Zoom
+    F             Fit the map to the window
+    P             Save a screenshot to the current directory
+    V             Show or hide the search
+    Escape        Quit"
+)]
+// The options of the window are left unread without it.
+#[cfg_attr(not(feature = "sdl"), allow(dead_code))]
 struct Opt {
+    /// Where to draw the map: "sdl" for a window, or "terminal" for text in the
+    /// terminal, which works over SSH without a display.
+    #[structopt(long, default_value = "sdl")]
+    renderer: Backend,
+
+    /// With the terminal renderer, draw one tile per character with plain `#`
+    /// and `.` characters instead of coloured half blocks.
+    #[structopt(long)]
+    ascii: bool,
+
     /// Activate fullscreen mode.
     #[structopt(short, long)]
     fullscreen: bool,
 
-    /// Turn on dark mode. Defaults to off.
+    /// Turn on dark mode. Defaults to off. Ignored if a theme is given.
     #[structopt(short, long)]
     dark_mode: bool,
 
+    /// Seed for the random number generator of the first map. Picked at random
+    /// if not given. The same seed and options always draw the same frames.
+    #[structopt(long)]
+    seed: Option<u64>,
+
+    /// Save a PNG of the window to this file once the first map is finished,
+    /// then quit.
+    #[structopt(long, parse(from_os_str))]
+    screenshot: Option<PathBuf>,
+
+    /// Save every frame until the first map is finished: as an animated GIF if
+    /// the path ends in `.gif`, and as numbered PNGs in a directory otherwise.
+    #[structopt(long, parse(from_os_str))]
+    record: Option<PathBuf>,
+
+    /// Colours to draw the map in: classic, dark, depth, template, or the path
+    /// of a JSON or TOML theme file.
+    #[structopt(long)]
+    theme: Option<Theme>,
+
+    /// What to colour rooms by, overriding the theme: tile, order, depth or
+    /// template.
+    #[structopt(long)]
+    color_by: Option<ColorMode>,
+
     /// Clears the screen and starts again when then screen is full.
     #[structopt(short, long)]
     restart: bool,
@@ -30,42 +108,600 @@ struct Opt {
     /// Height of the screen. Defaults to 728 if not fullscreen.
     #[structopt(short, long, required_if("width", "Some"))]
     height: Option<u32>,
+
+    /// Draw the search behind the map over it: the rooms waiting to be expanded,
+    /// the exits being tried, rejected candidates and the links between rooms.
+    #[structopt(long)]
+    show_search: bool,
+
+    /// Size of a tile in pixels. The map is made as large as fits in the window
+    /// at this size, but never smaller than the largest room.
+    #[structopt(long, default_value = "1", parse(try_from_str = tile_size))]
+    tile_size: f64,
+
+    /// What to do with entrances between rooms that end up touching along an edge
+    /// they were not attached through once the map is finished: "open" or "seal".
+    #[structopt(long)]
+    doors: Option<adjacency::DoorPolicy>,
+
+    /// How the edge a room shares with its parent has to line up: "any" only
+    /// checks the entrance it is attached through, "strict" rejects rooms whose
+    /// empty edge tiles face dirt, and "lenient" picks the room with the fewest.
+    #[structopt(long, default_value = "any")]
+    edges: map_gen::EdgeMatching,
+
+    /// What counts as rooms overlapping: "rect" compares bounding rectangles,
+    /// "tile-mask" lets rooms overlap where their tiles agree, such as dirt on dirt.
+    #[structopt(long, default_value = "rect")]
+    collision: map_gen::Collision,
+
+    /// What rooms are attached through: "tiles" lets a room attach to any single
+    /// tile of an opening, "sockets" only attaches whole openings onto openings
+    /// of the same width and type. An untyped opening never takes a typed one,
+    /// nor the other way around.
+    #[structopt(long, default_value = "tiles")]
+    entrances: map_gen::Entrances,
+
+    /// Fewest tiles of rock between rooms that are not attached to each other.
+    #[structopt(long, default_value = "0")]
+    min_gap: u32,
+
+    /// Extra rock to keep around rooms made from a template, on top of the
+    /// minimum gap, given as NAME=TILES. Can be given more than once.
+    #[structopt(long, number_of_values = 1)]
+    padding: Vec<Padding>,
+
+    /// Place a room by hand before generating the rest, given as NAME@X,Y with
+    /// the name of a template and the position of its top left corner. Can be
+    /// given more than once.
+    #[structopt(long, number_of_values = 1, conflicts_with = "solution-path")]
+    fixed_room: Vec<FixedRoom>,
+
+    /// Keep rooms out of a rectangle, given as X,Y,WIDTHxHEIGHT. Can be given
+    /// more than once.
+    #[structopt(long, number_of_values = 1)]
+    forbid: Vec<Zone>,
+
+    /// Grow the map within a shape instead of the whole screen, either `circle`
+    /// for the largest ellipse fitting on the screen or the path of a PNG image
+    /// whose bright pixels are the tiles rooms may cover.
+    #[structopt(long, conflicts_with = "solution-path")]
+    shape: Option<Shape>,
+
+    /// Only place rooms that a player under gravity can get into from the first
+    /// room, jumping and falling as far as the options below allow.
+    #[structopt(long)]
+    platformer: bool,
+
+    /// Tiles a platformer player can jump up.
+    #[structopt(long, default_value = "3")]
+    jump_height: u32,
+
+    /// Tiles a platformer player can jump or step sideways.
+    #[structopt(long, default_value = "4")]
+    jump_distance: u32,
+
+    /// Tiles a platformer player can fall without dying.
+    #[structopt(long, default_value = "8")]
+    fall_tolerance: u32,
+
+    /// Build the map around a path from an entrance at the top to an exit at the
+    /// bottom, winding through a grid of slots given as COLUMNSxROWS, with one
+    /// room in each slot. Corridors are not used.
+    #[structopt(long)]
+    solution_path: Option<Grid>,
+
+    /// Number of loops to try to close between branches once the map is finished.
+    #[structopt(long, default_value = "0")]
+    loops: usize,
+
+    /// Maximum length of corridors used to reach rooms that cannot be attached
+    /// directly. Corridors are only used if this is set.
+    #[structopt(long, parse(try_from_str = tiles))]
+    corridor_length: Option<u32>,
+
+    /// Width of the corridors.
+    #[structopt(long, default_value = "1", parse(try_from_str = tiles))]
+    corridor_width: u32,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Backend {
+    Sdl,
+    Terminal,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sdl" => Ok(Self::Sdl),
+            "terminal" => Ok(Self::Terminal),
+            _ => Err(format!(
+                "unknown renderer `{}`, expected sdl or terminal",
+                s
+            )),
+        }
+    }
+}
+
+/// Padding around the rooms made from one template.
+#[derive(Clone, Debug)]
+struct Padding {
+    room: String,
+    tiles: u32,
+}
+
+impl FromStr for Padding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (room, tiles) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected NAME=TILES, got `{}`", s))?;
+        let tiles = tiles
+            .parse()
+            .map_err(|_| format!("`{}` is not a number of tiles", tiles))?;
+        Ok(Self {
+            room: room.to_string(),
+            tiles,
+        })
+    }
+}
+
+/// A template placed by hand.
+#[derive(Clone, Debug)]
+struct FixedRoom {
+    name: String,
+    pos: Point2<i32>,
+}
+
+impl FromStr for FixedRoom {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, pos) = s
+            .split_once('@')
+            .ok_or_else(|| format!("expected NAME@X,Y, got `{}`", s))?;
+        if map_gen::template(name).is_none() {
+            return Err(format!("unknown room template `{}`", name));
+        }
+        let (x, y) = pos
+            .split_once(',')
+            .ok_or_else(|| format!("expected X,Y, got `{}`", pos))?;
+        Ok(Self {
+            name: name.to_string(),
+            pos: Point2::new(coordinate(x)?, coordinate(y)?),
+        })
+    }
+}
+
+/// A rectangle rooms are kept out of.
+#[derive(Copy, Clone, Debug)]
+struct Zone(Rect);
+
+impl FromStr for Zone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(',').collect();
+        let size = parts.get(2).and_then(|size| size.split_once('x'));
+        match (parts.len(), size) {
+            (3, Some((width, height))) => {
+                let min = Point2::new(coordinate(parts[0])?, coordinate(parts[1])?);
+                let size = Vector2::new(coordinate(width)?, coordinate(height)?);
+                Ok(Self(Rect::new(min, min + size)))
+            }
+            _ => Err(format!("expected X,Y,WIDTHxHEIGHT, got `{}`", s)),
+        }
+    }
+}
+
+/// The shape the map is grown within.
+#[derive(Clone, Debug)]
+enum Shape {
+    Circle,
+    Image(Mask),
+}
+
+impl FromStr for Shape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "circle" => Ok(Self::Circle),
+            path => Mask::from_png(path.as_ref()).map(Self::Image),
+        }
+    }
+}
+
+fn coordinate(s: &str) -> Result<i32, String> {
+    s.parse()
+        .map_err(|_| format!("`{}` is not a number of tiles", s))
+}
+
+/// Parses a size that has to be at least a tile.
+fn tiles(s: &str) -> Result<u32, String> {
+    match s.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("`{}` is not a positive number of tiles", s)),
+    }
+}
+
+/// Parses a size in pixels that has to be larger than zero.
+fn tile_size(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(n) if n.is_finite() && n > 0.0 => Ok(n),
+        _ => Err(format!("`{}` is not a positive number of pixels", s)),
+    }
+}
+
+/// Size of a grid of slots.
+#[derive(Copy, Clone, Debug)]
+struct Grid {
+    columns: u32,
+    rows: u32,
+}
+
+impl FromStr for Grid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (columns, rows) = s
+            .split_once('x')
+            .ok_or_else(|| format!("expected COLUMNSxROWS, got `{}`", s))?;
+        let parse = |n: &str| match n.parse() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(format!("`{}` is not a positive number of slots", n)),
+        };
+        Ok(Self {
+            columns: parse(columns)?,
+            rows: parse(rows)?,
+        })
+    }
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Generate a map without opening a window and print statistics about it as
+    /// JSON.
+    Stats {
+        /// Seed for the random number generator. Picked at random if not given.
+        #[structopt(long)]
+        seed: Option<u64>,
+
+        /// Write every step of the search to standard error.
+        #[structopt(long)]
+        trace: bool,
+    },
+
+    /// Generate a map without opening a window and write it as plain text.
+    Export {
+        /// Seed for the random number generator. Picked at random if not given.
+        #[structopt(long)]
+        seed: Option<u64>,
+
+        /// Also write the origin and every room, so that the rooms can be
+        /// recovered when the map is read back.
+        #[structopt(long)]
+        annotate: bool,
+
+        /// File to write the map to. Defaults to standard output.
+        #[structopt(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Generate maps for many seeds without opening a window and write their
+    /// statistics as CSV, flagging the seeds that produce outliers.
+    BenchGen {
+        /// Number of maps to generate.
+        #[structopt(long, default_value = "100")]
+        seeds: u64,
+
+        /// Seed of the first map. The rest use the seeds following it.
+        #[structopt(long, default_value = "0")]
+        first_seed: u64,
+
+        /// Number of threads to generate maps on. Defaults to one per CPU.
+        #[structopt(long)]
+        threads: Option<usize>,
+
+        /// File to write the CSV to. Defaults to standard output.
+        #[structopt(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Pixels the map moves by per arrow key press.
+#[cfg(feature = "sdl")]
+const PAN_STEP: f64 = 32.0;
+
+/// Factor the zoom changes by per mouse wheel step.
+#[cfg(feature = "sdl")]
+const ZOOM_STEP: f64 = 1.25;
+
+/// Rooms placed per frame at each fast-forward speed. The last places every room
+/// at once.
+#[cfg(feature = "sdl")]
+const SPEEDS: [usize; 5] = [1, 4, 16, 64, usize::MAX];
+
+/// Creates a map generator configured by the command line options.
+fn map_generator<R: Rng>(opt: &Opt, width: u32, height: u32, rng: R) -> map_gen::MapGenerator<R> {
+    let mut map_gen = map_gen::MapGenerator::new(width, height, rng)
+        .with_loops(opt.loops)
+        .with_edge_matching(opt.edges)
+        .with_collision(opt.collision)
+        .with_entrances(opt.entrances)
+        .with_min_gap(opt.min_gap);
+    for padding in &opt.padding {
+        map_gen = map_gen.with_padding(&padding.room, padding.tiles);
+    }
+    if let Some(policy) = opt.doors {
+        map_gen = map_gen.with_doors(policy);
+    }
+    for zone in &opt.forbid {
+        map_gen = map_gen.with_forbidden_rect(zone.0);
+    }
+    if opt.platformer {
+        map_gen = map_gen.with_movement(Movement {
+            jump_height: opt.jump_height,
+            jump_distance: opt.jump_distance,
+            fall_tolerance: opt.fall_tolerance,
+        });
+    }
+    if let Some(grid) = opt.solution_path {
+        return map_gen.with_solution_path(grid.columns, grid.rows);
+    }
+    for fixed in &opt.fixed_room {
+        let room = map_gen::template(&fixed.name).unwrap().clone();
+        map_gen = map_gen.with_fixed_room(room, fixed.pos);
+    }
+    match &opt.shape {
+        Some(Shape::Circle) => map_gen = map_gen.with_shape(Mask::ellipse(width, height)),
+        Some(Shape::Image(mask)) => map_gen = map_gen.with_shape(mask.clone()),
+        None => (),
+    }
+    match opt.corridor_length {
+        Some(len) => map_gen.with_corridors(opt.corridor_width, len),
+        None => map_gen,
+    }
+}
+
+/// The theme picked by the command line options.
+fn theme(opt: &Opt) -> Theme {
+    let mut theme = opt.theme.clone().unwrap_or_else(|| {
+        Theme::built_in(if opt.dark_mode { "dark" } else { "classic" }).unwrap()
+    });
+    if let Some(mode) = opt.color_by {
+        theme.mode = mode;
+    }
+    theme
+}
+
+/// Size of the terminal in characters, leaving a line for the status.
+fn terminal_size() -> (u32, u32) {
+    terminal_size::terminal_size().map_or((80, 23), |(w, h)| {
+        (w.0 as u32, (h.0 as u32).saturating_sub(1).max(1))
+    })
+}
+
+/// Animates maps being generated in the terminal, the same way as in the window.
+fn run_terminal(opt: &Opt) {
+    let charset = if opt.ascii {
+        Charset::Ascii
+    } else {
+        Charset::HalfBlock
+    };
+    let theme = theme(opt);
+    let (cols, rows) = terminal_size();
+    let (width, height) = (cols, charset.tile_rows(rows));
+    let mut seed = opt.seed.unwrap_or_else(rand::random);
+    let mut out = io::stdout().lock();
+    let mut show = |scene: &Scene, seed: u64| {
+        let (cols, rows) = terminal_size();
+        let frame = terminal::draw(scene, theme.dirt, charset, cols, rows);
+        write!(
+            out,
+            "{}seed {} - {} rooms\x1b[K",
+            frame,
+            seed,
+            scene.placements().len()
+        )
+        .and_then(|()| out.flush())
+        .unwrap();
+        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+    };
+
+    print!("\x1b[2J");
+    loop {
+        let mut scene = Scene::default();
+        let map_gen = map_generator(opt, width, height, StdRng::seed_from_u64(seed));
+        for (order, room) in map_gen.enumerate() {
+            let color = theme.room_color(&scene, &room, order);
+            scene.add(room, color);
+            show(&scene, seed);
+        }
+        if !opt.restart {
+            break;
+        }
+        seed = rand::random();
+    }
+    println!();
+}
+
+fn export(
+    opt: &Opt,
+    width: u32,
+    height: u32,
+    seed: Option<u64>,
+    annotate: bool,
+    output: Option<&PathBuf>,
+) {
+    let rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut map_gen = map_generator(opt, width, height, rng);
+    map_gen.by_ref().for_each(drop);
+    let text = text_map::export(map_gen.placements(), annotate).unwrap_or_else(|e| {
+        eprintln!("cannot export the map: {}", e);
+        std::process::exit(1)
+    });
+    match output {
+        Some(path) => std::fs::write(path, text).unwrap(),
+        None => print!("{}", text),
+    }
+}
+
+fn print_stats(opt: &Opt, width: u32, height: u32, seed: Option<u64>, trace: bool) {
+    let rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let start = Instant::now();
+    let log: Box<dyn io::Write> = if trace {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::sink())
+    };
+    let mut map_gen = map_generator(opt, width, height, rng).with_observer(Log(log));
+    map_gen.by_ref().for_each(drop);
+    let stats = MapStats::compute(map_gen.placements(), start.elapsed());
+    println!("{}", serde_json::to_string_pretty(&stats).unwrap());
+    if opt.shape.is_some() {
+        eprintln!(
+            "{} exits left dangling at the edge of the shape",
+            map_gen.dangling_exits().len()
+        );
+    }
+}
+
+fn bench_gen(
+    opt: &Opt,
+    width: u32,
+    height: u32,
+    seeds: std::ops::Range<u64>,
+    threads: Option<usize>,
+    output: Option<&PathBuf>,
+) {
+    let threads =
+        threads.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    let results = batch::generate(seeds, threads, |rng| map_generator(opt, width, height, rng));
+    match output {
+        Some(path) => batch::write_csv(&results, File::create(path).unwrap()),
+        None => batch::write_csv(&results, io::stdout().lock()),
+    }
+    .unwrap();
+
+    eprintln!(
+        "{:<22}{:>12}{:>12}{:>12}{:>12}{:>12}{:>12}",
+        "metric", "min", "p50", "p90", "p99", "max", "mean"
+    );
+    for (name, s) in batch::summarize(&results) {
+        eprintln!(
+            "{:<22}{:>12.3}{:>12.3}{:>12.3}{:>12.3}{:>12.3}{:>12.3}",
+            name, s.min, s.p50, s.p90, s.p99, s.max, s.mean
+        );
+    }
+    let outliers = results.iter().filter(|r| !r.outliers.is_empty()).count();
+    eprintln!("{} of {} seeds produced outliers", outliers, results.len());
 }
 
 fn main() {
     let opt = Opt::from_args();
 
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
+    let window_size = (opt.width.unwrap_or(1024), opt.height.unwrap_or(728));
+    // The camera keeps the tile size within the zoom range.
+    let camera = Camera::new(opt.tile_size);
+    let width = ((window_size.0 as f64 / camera.zoom) as u32).max(map_gen::min_map_size());
+    let height = ((window_size.1 as f64 / camera.zoom) as u32).max(map_gen::min_map_size());
+
+    match &opt.command {
+        Some(Command::Stats { seed, trace }) => {
+            return print_stats(&opt, width, height, *seed, *trace)
+        }
+        Some(Command::Export {
+            seed,
+            annotate,
+            output,
+        }) => return export(&opt, width, height, *seed, *annotate, output.as_ref()),
+        Some(Command::BenchGen {
+            seeds,
+            first_seed,
+            threads,
+            output,
+        }) => {
+            let last_seed = first_seed.checked_add(*seeds).unwrap_or_else(|| {
+                clap::Error::with_description(
+                    "--first-seed and --seeds go past the largest seed",
+                    clap::ErrorKind::ValueValidation,
+                )
+                .exit()
+            });
+            return bench_gen(
+                &opt,
+                width,
+                height,
+                *first_seed..last_seed,
+                *threads,
+                output.as_ref(),
+            );
+        }
+        None => (),
+    }
+    if let Backend::Terminal = opt.renderer {
+        return run_terminal(&opt);
+    }
+    run_window(&opt, width, height, window_size, camera);
+}
 
+/// Animates maps being generated in a window, with the controls listed in the
+/// help.
+#[cfg(feature = "sdl")]
+fn run_window(opt: &Opt, width: u32, height: u32, mut window_size: (u32, u32), camera: Camera) {
     let override_resolution = opt.width.is_some();
-    let width = opt.width.unwrap_or(1024);
-    let height = opt.height.unwrap_or(728);
+    let sdl_context = sdl2::init().unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
 
-    let mut window = video_subsystem.window("dungeon-explorer", width, height);
+    let mut window = video_subsystem.window("dungeon-explorer", window_size.0, window_size.1);
     let window = match (opt.fullscreen, override_resolution) {
         (true, false) => window.fullscreen_desktop(),
         (true, true) => window.fullscreen(),
-        _ => &mut window,
+        _ => window.resizable(),
     };
     let window = window.position_centered().build().unwrap();
 
     let mut canvas = window.into_canvas().build().unwrap();
+    let texture_creator = canvas.texture_creator();
 
-    let mut map_gen = map_gen::MapGenerator::new(width, height, rand::thread_rng());
+    let mut seed = opt.seed.unwrap_or_else(rand::random);
+    let mut map_gen = map_generator(opt, width, height, StdRng::seed_from_u64(seed))
+        .with_observer(SearchOverlay::default());
 
-    let dirt_color = if opt.dark_mode {
-        (0, 0, 0)
-    } else {
-        (u8::MAX, u8::MAX, u8::MAX)
-    };
+    let theme = theme(opt);
+    let mut renderer = Renderer::new(&texture_creator, theme.dirt, camera);
 
-    canvas.set_draw_color(Color::RGB(dirt_color.0, dirt_color.1, dirt_color.2));
-    canvas.clear();
-    canvas.present();
+    let mut recorder = opt.record.as_ref().map(|path| {
+        let (w, h) = canvas.output_size().unwrap();
+        Recorder::new(path, w, h).unwrap()
+    });
+    let mut screenshots = 0;
 
     let mut event_pump = sdl_context.event_pump().unwrap();
-    let mut i = 0;
+    let mut order = 0;
+    let mut scene = Scene::default();
+    let mut finished = false;
+    let mut mouse = Point2::new(0.0, 0.0);
+    let mut show_search = opt.show_search;
+    let mut paused = false;
+    let mut pending_steps = 0;
+    let mut speed = 0;
+    let mut regenerate = None;
+    let mut take_screenshot = false;
     'running: loop {
         for event in event_pump.poll_iter() {
             match event {
@@ -74,26 +710,142 @@ fn main() {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(w, h),
+                    ..
+                } => {
+                    let new_size = (w as u32, h as u32);
+                    renderer.camera_mut().resize(window_size, new_size);
+                    window_size = new_size;
+                }
+                Event::MouseWheel { y, .. } => {
+                    renderer.camera_mut().zoom_at(mouse, ZOOM_STEP.powi(y));
+                }
+                Event::MouseMotion {
+                    mousestate,
+                    x,
+                    y,
+                    xrel,
+                    yrel,
+                    ..
+                } => {
+                    mouse = Point2::new(x as f64, y as f64);
+                    if mousestate.left() {
+                        renderer
+                            .camera_mut()
+                            .pan(Vector2::new(xrel as f64, yrel as f64));
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } => match key {
+                    Keycode::Left => renderer.camera_mut().pan(Vector2::new(PAN_STEP, 0.0)),
+                    Keycode::Right => renderer.camera_mut().pan(Vector2::new(-PAN_STEP, 0.0)),
+                    Keycode::Up => renderer.camera_mut().pan(Vector2::new(0.0, PAN_STEP)),
+                    Keycode::Down => renderer.camera_mut().pan(Vector2::new(0.0, -PAN_STEP)),
+                    Keycode::F => {
+                        if let Some(bounds) = scene.bounds() {
+                            renderer.camera_mut().fit(&bounds, window_size);
+                        }
+                    }
+                    Keycode::P => take_screenshot = true,
+                    Keycode::V => show_search = !show_search,
+                    Keycode::Space => paused = !paused,
+                    Keycode::Period => {
+                        paused = true;
+                        pending_steps += 1;
+                    }
+                    Keycode::Equals | Keycode::KpPlus => speed = (speed + 1).min(SPEEDS.len() - 1),
+                    Keycode::Minus | Keycode::KpMinus => speed = speed.saturating_sub(1),
+                    Keycode::N => regenerate = Some(rand::random()),
+                    Keycode::R => regenerate = Some(seed),
+                    _ => {}
+                },
                 _ => {}
             }
         }
 
-        i = (i + 1) % 255;
-        if let Some(room) = map_gen.next() {
-            room.draw(
-                &mut canvas,
-                (i as u8, rand::random(), (255 - i) as u8),
-                dirt_color,
-            )
-        } else if opt.restart {
-            canvas.set_draw_color(Color::RGB(dirt_color.0, dirt_color.1, dirt_color.2));
-            drop(std::mem::replace(
-                &mut map_gen,
-                map_gen::MapGenerator::new(width, height, rand::thread_rng()),
-            ));
+        let steps = if paused {
+            std::mem::take(&mut pending_steps)
+        } else {
+            SPEEDS[speed]
+        };
+        let mut just_finished = false;
+        let progress = map_gen.step_for(steps);
+        for room in progress.rooms {
+            let color = theme.room_color(&scene, &room, order);
+            order += 1;
+            // Connectors replace the dead end with the same id.
+            scene.add(room, color);
+        }
+        let exhausted = progress.finished;
+        if exhausted && !finished {
+            finished = true;
+            just_finished = true;
+        } else if exhausted && opt.restart {
+            regenerate = Some(rand::random());
+        }
+
+        if let Some(new_seed) = regenerate.take() {
+            seed = new_seed;
+            // A new overlay as well, so that nothing the search did on the
+            // previous map is drawn over the new one.
+            map_gen = map_generator(opt, width, height, StdRng::seed_from_u64(seed))
+                .with_observer(SearchOverlay::default());
+            scene.clear();
+            renderer.clear();
+            order = 0;
+            finished = false;
+        }
+
+        let title = format!(
+            "dungeon-explorer - seed {} - {} rooms",
+            seed,
+            scene.placements().len()
+        );
+        if canvas.window().title() != title {
+            canvas.window_mut().set_title(&title).unwrap();
         }
 
+        scene.draw(&mut canvas, &mut renderer);
+        if show_search {
+            map_gen
+                .observer()
+                .draw(&mut canvas, &renderer, &scene, map_gen.frontier());
+        }
+        map_gen.observer_mut().tick();
+
+        if let Some(rec) = &mut recorder {
+            if let Err(e) = rec.record(&Image::from_canvas(&canvas)) {
+                eprintln!("stopped recording: {}", e);
+                recorder = None;
+            } else if just_finished {
+                recorder = None;
+            }
+        }
+        if take_screenshot {
+            take_screenshot = false;
+            screenshots += 1;
+            let path = PathBuf::from(format!("dungeon-explorer-{}-{}.png", seed, screenshots));
+            match Image::from_canvas(&canvas).save_png(&path) {
+                Ok(()) => eprintln!("saved {}", path.display()),
+                Err(e) => eprintln!("cannot save {}: {}", path.display(), e),
+            }
+        }
+        if let (true, Some(path)) = (just_finished, &opt.screenshot) {
+            Image::from_canvas(&canvas).save_png(path).unwrap();
+            break;
+        }
         canvas.present();
         ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
     }
 }
+
+#[cfg(not(feature = "sdl"))]
+fn run_window(_: &Opt, _: u32, _: u32, _: (u32, u32), _: Camera) {
+    clap::Error::with_description(
+        "built without the sdl feature, use --renderer terminal or a subcommand",
+        clap::ErrorKind::InvalidValue,
+    )
+    .exit()
+}
@@ -1,18 +1,39 @@
 //! Tile-based dungeon map generation.
+use crate::adjacency::DoorPolicy;
 use crate::kd_tree::KDTree;
+use crate::mask::Mask;
+use crate::observer::{Observer, RejectReason};
+use crate::reachability::{Movement, Reach};
 use crate::rect::Rect;
+use crate::stats::MapStats;
+use crate::world::Region;
 use cgmath::*;
 use lazy_static::lazy_static;
 use rand::prelude::*;
-use sdl2::pixels::PixelFormatEnum;
-use sdl2::rect;
-use sdl2::render::Canvas;
-use sdl2::surface::Surface;
-use sdl2::video::Window;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::Instant;
+
+mod collision;
+mod corridors;
+mod doors;
+mod edges;
+mod incremental;
+mod loops;
+mod path;
+mod platforms;
+mod shape;
+mod sockets;
+
+pub use collision::Collision;
+pub use edges::EdgeMatching;
+pub use incremental::{Budget, Progress};
+pub use shape::DanglingExit;
+pub use sockets::{Entrances, Socket};
 
 /// Type of the tile.
 // TODO(map): Add more in the future, the possibilities are endless!
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Tile {
     Empty,
     Dirt,
@@ -24,13 +45,13 @@ const E: Tile = Tile::Empty;
 const D: Tile = Tile::Dirt;
 
 impl Tile {
-    fn is_empty(&self) -> bool {
+    pub(crate) fn is_empty(&self) -> bool {
         matches!(self, Self::Empty)
     }
 }
 
 /// A cardinal direction.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Direction {
     North = 0,
     East = 1,
@@ -38,8 +59,26 @@ pub enum Direction {
     West = 3,
 }
 
+/// All cardinal directions, in the order of their discriminants.
+pub(crate) const CARDINALS: [Direction; 4] = [
+    Direction::North,
+    Direction::East,
+    Direction::South,
+    Direction::West,
+];
+
 impl Direction {
-    fn flip(self) -> Direction {
+    /// Unit vector pointing in this direction, with y growing southwards.
+    pub(crate) fn offset(self) -> Vector2<i32> {
+        match self {
+            Self::North => Vector2::new(0, -1),
+            Self::East => Vector2::new(1, 0),
+            Self::South => Vector2::new(0, 1),
+            Self::West => Vector2::new(-1, 0),
+        }
+    }
+
+    pub(crate) fn flip(self) -> Direction {
         match self {
             Self::North => Self::South,
             Self::East => Self::West,
@@ -53,16 +92,29 @@ impl Direction {
 /// directions.
 #[derive(Clone, Debug)]
 pub struct Room {
-    width: u32,
-    height: u32,
+    /// Name of the template the room was created from.
+    pub(crate) name: String,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
     /// Goes from top to bottom, west to east.
-    layout: &'static [&'static [Tile]],
+    pub(crate) layout: Vec<Vec<Tile>>,
     /// List of open edges per each cardinal direction.
-    entrances: [Vec<i32>; 4],
+    pub(crate) entrances: [Vec<i32>; 4],
+    /// The entrances per each cardinal direction, grouped into spans of
+    /// adjacent tiles.
+    pub(crate) sockets: [Vec<Socket>; 4],
 }
 
 impl Room {
-    fn new(layout: &'static [&'static [Tile]]) -> Self {
+    pub fn new(name: &str, layout: &[&[Tile]]) -> Self {
+        Self::from_layout(
+            name.to_string(),
+            layout.iter().map(|row| row.to_vec()).collect(),
+        )
+    }
+
+    /// Creates a room from rows of tiles, going from top to bottom, west to east.
+    pub fn from_layout(name: String, layout: Vec<Vec<Tile>>) -> Self {
         let width = layout[0].len();
         let height = layout.len();
         // Find all of the entrances.
@@ -90,23 +142,63 @@ impl Room {
             .filter(|(_, t)| t[0].is_empty())
             .map(|(i, _)| i as i32)
             .collect();
-        // Create a texture for this.
+        let entrances = [
+            north_entrances,
+            east_entrances,
+            south_entrances,
+            west_entrances,
+        ];
+        let sockets = [
+            sockets::spans(&entrances[0]),
+            sockets::spans(&entrances[1]),
+            sockets::spans(&entrances[2]),
+            sockets::spans(&entrances[3]),
+        ];
         Self {
+            name,
             width: width as u32,
             height: height as u32,
             layout,
-            entrances: [
-                north_entrances,
-                east_entrances,
-                south_entrances,
-                west_entrances,
-            ],
+            entrances,
+            sockets,
+        }
+    }
+
+    /// Gives the socket starting at `start` on the `side` edge a type, so that
+    /// only sockets of the same type fit onto it.
+    pub fn with_socket_kind(mut self, side: Direction, start: i32, kind: &str) -> Self {
+        let socket = self.sockets[side as usize]
+            .iter_mut()
+            .find(|s| s.start == start)
+            .expect("no socket starts there");
+        socket.kind = Some(kind.to_string());
+        self
+    }
+
+    /// Gives each socket that `from` has as well, starting at the same tile and
+    /// just as wide, the type it has in `from`. Other sockets lose their types.
+    pub(crate) fn copy_socket_kinds(&mut self, from: &Room) {
+        for (side, sockets) in self.sockets.iter_mut().enumerate() {
+            for socket in sockets {
+                socket.kind = from.sockets[side]
+                    .iter()
+                    .find(|s| (s.start, s.width) == (socket.start, socket.width))
+                    .and_then(|s| s.kind.clone());
+            }
         }
     }
 
-    fn place(&self, pos: Point2<i32>) -> RoomPlacement {
+    pub(crate) fn place(
+        &self,
+        pos: Point2<i32>,
+        id: usize,
+        parent: Option<usize>,
+    ) -> RoomPlacement {
         RoomPlacement {
             pos,
+            id,
+            parent,
+            joins: None,
             // It would be nice to avoid this clone with an Rc, but that doesn't
             // play well with lazy_static, so it would need to be an Arc, which
             // seems excessive here.
@@ -115,57 +207,83 @@ impl Room {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct RoomPlacement {
     pub pos: Point2<i32>,
     pub room: Room,
+    /// Order in which the room was placed by the generator, starting at zero for
+    /// the first room.
+    pub id: usize,
+    /// Id of the room this one was attached to. None for the first room.
+    pub parent: Option<usize>,
+    /// For connectors inserted to close a loop, the id of the room on another
+    /// branch that this one opens into.
+    pub joins: Option<usize>,
 }
 
 impl RoomPlacement {
-    pub fn draw(
-        &self,
-        canvas: &mut Canvas<Window>,
-        empty_color: (u8, u8, u8),
-        dirt_color: (u8, u8, u8),
-    ) {
-        let mut text: Vec<_> = self
-            .room
-            .layout
-            .iter()
-            .map(|t| {
-                t.iter().map(|t| {
-                    if t.is_empty() {
-                        [empty_color.0, empty_color.1, empty_color.2]
-                    } else {
-                        [dirt_color.0, dirt_color.1, dirt_color.2]
-                    }
-                })
-            })
-            .flatten()
-            .flat_map(|b| b.to_vec())
-            .collect();
-        let surface = Surface::from_data(
-            &mut text[..],
-            self.room.width as u32,
-            self.room.height as u32,
-            self.room.width as u32 * 3,
-            PixelFormatEnum::RGB24,
-        )
-        .unwrap();
-        let texture_creator = canvas.texture_creator();
-        let texture = surface.as_texture(&texture_creator).unwrap();
-        canvas
-            .copy(
-                &texture,
-                None,
-                Some(rect::Rect::new(
-                    self.pos.x as i32,
-                    self.pos.y as i32,
-                    self.room.width as u32,
-                    self.room.height as u32,
-                )),
-            )
-            .unwrap();
+    /// The bounding rectangle of the placed room.
+    pub fn rect(&self) -> Rect {
+        Rect {
+            min: self.pos,
+            max: self.pos + Vector2::new(self.room.width as i32, self.room.height as i32),
+        }
+    }
+
+    /// Returns the tile at the given map position. The position must lie within
+    /// the room's rectangle.
+    pub fn tile_at(&self, pos: Point2<i32>) -> Tile {
+        let local = pos - self.pos;
+        self.room.layout[local.y as usize][local.x as usize]
+    }
+
+    /// Map position of the border tile of the entrance at `index` along the
+    /// `side` edge of the room.
+    pub(crate) fn entrance_tile(&self, side: Direction, index: i32) -> Point2<i32> {
+        let (w, h) = (self.room.width as i32, self.room.height as i32);
+        self.pos
+            + match side {
+                Direction::North => Vector2::new(index, 0),
+                Direction::East => Vector2::new(w - 1, index),
+                Direction::South => Vector2::new(index, h - 1),
+                Direction::West => Vector2::new(0, index),
+            }
+    }
+
+    /// Position to place `room` at so that its `entrance` on the opposite side
+    /// lines up with this room's `exit` on the `side` edge.
+    fn attach_pos(&self, side: Direction, exit: i32, room: &Room, entrance: i32) -> Point2<i32> {
+        match side {
+            Direction::North => {
+                Point2::new(self.pos.x + exit, self.pos.y)
+                    + Vector2::new(-entrance, -(room.height as i32))
+            }
+            Direction::East => {
+                Point2::new(self.pos.x + self.room.width as i32, self.pos.y + exit)
+                    + Vector2::new(0, -entrance)
+            }
+            Direction::South => {
+                Point2::new(self.pos.x + exit, self.pos.y + self.room.height as i32)
+                    + Vector2::new(-entrance, 0)
+            }
+            Direction::West => {
+                Point2::new(self.pos.x, self.pos.y + exit)
+                    + Vector2::new(-(room.width as i32), -entrance)
+            }
+        }
+    }
+}
+
+/// Records every border tile of the placement as belonging to it.
+pub(crate) fn add_border(borders: &mut HashMap<Point2<i32>, usize>, placement: &RoomPlacement) {
+    let r = placement.rect();
+    for x in r.min.x..r.max.x {
+        borders.insert(Point2::new(x, r.min.y), placement.id);
+        borders.insert(Point2::new(x, r.max.y - 1), placement.id);
+    }
+    for y in r.min.y..r.max.y {
+        borders.insert(Point2::new(r.min.x, y), placement.id);
+        borders.insert(Point2::new(r.max.x - 1, y), placement.id);
     }
 }
 
@@ -176,44 +294,402 @@ impl RoomPlacement {
 /// of rectangular spaces of tiles called "rooms".
 ///
 #[derive(Debug)]
-pub struct MapGenerator<R: Rng> {
+pub struct MapGenerator<R: Rng, O: Observer = ()> {
     width: u32,
     height: u32,
     room_stack: Vec<RoomPlacement>,
     prev_placed: KDTree,
+    /// Every room placed so far, indexed by id.
+    placed: Vec<RoomPlacement>,
+    /// Number of loops to close once the depth-first search is exhausted.
+    target_loops: usize,
+    /// Connectors waiting to be returned once the loops have been closed,
+    /// followed by rooms whose doors were opened or sealed after that.
+    connectors: Option<Vec<RoomPlacement>>,
+    /// What to do with entrances between rooms that touch by accident.
+    doors: Option<DoorPolicy>,
+    /// Corridor templates to bridge exits that no room fits directly onto.
+    corridors: Vec<Room>,
+    edge_matching: EdgeMatching,
+    collision: Collision,
+    /// Tiles of every placed room, when collisions are decided by tile or the
+    /// player's movement is checked.
+    occupied: HashMap<Point2<i32>, Tile>,
+    /// Fewest tiles of rock between rooms that are not connected.
+    min_gap: i32,
+    /// Extra rock around rooms, by template name.
+    padding: HashMap<String, i32>,
+    entrances: Entrances,
+    /// How the player moves, when every room has to be reachable.
+    movement: Option<Movement>,
+    /// Tiles the player can stand on in each room, by id.
+    reached: HashMap<usize, Vec<Point2<i32>>>,
+    /// Where the player can get to on the map placed so far.
+    reach: Reach,
+    /// The last candidate checked against `reach`, with what `reach_with`
+    /// made of it, so that it is not worked out again once it is placed.
+    checked: RefCell<Option<(RoomPlacement, Reach, bool)>>,
+    /// Columns and rows of the grid of slots, when the map is built around a
+    /// solution path instead of grown outwards from the first room.
+    slot_grid: Option<(u32, u32)>,
+    /// Ids of the rooms on the solution path from the entrance to the exit, once
+    /// the slots have been filled.
+    solution: Option<Vec<usize>>,
+    /// Where rooms may be placed, when generating a chunk of a world rather than
+    /// a map on the screen.
+    region: Option<Region>,
+    /// Ids of the rooms placed by hand, which are never replaced.
+    fixed: Vec<usize>,
+    /// Rectangles that rooms are kept out of.
+    forbidden: Vec<Rect>,
+    /// Bitmaps of tiles that rooms are kept out of.
+    forbidden_masks: Vec<Mask>,
+    /// Tiles the map is grown within instead of the screen.
+    shape: Option<Mask>,
+    /// Exits that were left open because the rooms fitting onto them leave the
+    /// shape.
+    dangling: Vec<DanglingExit>,
     rng: R,
+    observer: O,
+    /// When the generator was created, for the statistics reported once it is
+    /// finished.
+    started: Instant,
+    finished: bool,
 }
 
 impl<R: Rng> MapGenerator<R> {
-    /// Creates a new map generator.
+    /// Creates a new map generator. Neither side of the map may be shorter than
+    /// [`min_map_size`].
     pub fn new(width: u32, height: u32, rng: R) -> Self {
-        let first_room = Room::new(&[
-            &[E, E, E, E, E, E, E, E, E, E],
-            &[E, E, E, E, E, E, E, E, E, E],
-            &[E, D, D, D, E, D, D, D, D, E],
-            &[E, D, D, E, E, E, E, D, D, E],
-            &[E, D, E, E, E, E, E, D, E, E],
-            &[E, D, E, E, E, E, E, D, E, E],
-            &[E, D, E, E, E, E, E, D, E, E],
-            &[E, D, D, E, E, E, E, D, E, E],
-            &[E, D, D, D, E, E, E, D, D, E],
-            &[D, D, D, D, E, E, D, D, D, D],
-        ]);
+        let first_room = &*START_ROOM;
         let start_x = ((width - first_room.width) / 2) as i32;
         let start_y = ((height - first_room.height) / 2) as i32;
+        let first_placement = first_room.place(Point2::new(start_x, start_y), 0, None);
         let mut kd_tree = KDTree::default();
-        kd_tree.add_rect(Rect {
-            min: Point2::new(start_x, start_y),
-            max: Point2::new(start_x, start_y)
-                + Vector2::new(first_room.width as i32, first_room.height as i32),
-        });
+        kd_tree.add_rect(first_placement.rect());
         Self {
             width,
             height,
-            room_stack: vec![first_room.place(Point2::new(start_x, start_y))],
+            room_stack: vec![first_placement.clone()],
             prev_placed: kd_tree,
+            placed: vec![first_placement],
+            target_loops: 0,
+            connectors: None,
+            doors: None,
+            corridors: Vec::new(),
+            edge_matching: EdgeMatching::Any,
+            collision: Collision::Rect,
+            occupied: HashMap::new(),
+            min_gap: 0,
+            padding: HashMap::new(),
+            entrances: Entrances::Tiles,
+            movement: None,
+            reached: HashMap::new(),
+            reach: Reach::default(),
+            checked: RefCell::new(None),
+            slot_grid: None,
+            solution: None,
+            region: None,
+            fixed: Vec::new(),
+            forbidden: Vec::new(),
+            forbidden_masks: Vec::new(),
+            shape: None,
+            dangling: Vec::new(),
             rng,
+            observer: (),
+            started: Instant::now(),
+            finished: false,
+        }
+    }
+}
+
+impl<R: Rng, O: Observer> MapGenerator<R, O> {
+    /// Once no more rooms can be placed, try to close `target` loops by replacing
+    /// dead ends with connecting rooms that also open into another branch of the
+    /// map.
+    pub fn with_loops(mut self, target: usize) -> Self {
+        self.target_loops = target;
+        self
+    }
+
+    /// When no room can be attached to an exit directly, try to reach one through
+    /// a straight or L-shaped corridor `width` tiles wide with legs of up to
+    /// `max_length` tiles. Panics if either is zero.
+    pub fn with_corridors(mut self, width: u32, max_length: u32) -> Self {
+        self.corridors = corridors::corridor_library(width, max_length);
+        self
+    }
+
+    /// Opens or seals the entrances between rooms that end up touching along an
+    /// edge neither was attached through, once the map is finished. The rooms
+    /// that change are returned again at the end, replacing the ones with the
+    /// same id.
+    pub fn with_doors(mut self, policy: DoorPolicy) -> Self {
+        self.doors = Some(policy);
+        self
+    }
+
+    /// Decides how the rest of the edge a room shares with its parent has to line
+    /// up, besides the exit it is attached through.
+    pub fn with_edge_matching(mut self, edge_matching: EdgeMatching) -> Self {
+        self.edge_matching = edge_matching;
+        self
+    }
+
+    /// Decides what counts as rooms overlapping. With `Collision::TileMask`,
+    /// rooms may overlap where their tiles agree, so the KD-tree that narrows
+    /// down the rooms to compare holds overlapping rectangles and is slower.
+    pub fn with_collision(mut self, collision: Collision) -> Self {
+        self.collision = collision;
+        self.occupied.clear();
+        for placement in self.placed.clone() {
+            self.occupy(&placement);
         }
+        self
+    }
+
+    /// Keeps at least `gap` tiles of rock between rooms, except between a room
+    /// and the one it is attached to. Connectors closing loops are only kept
+    /// from overlapping.
+    pub fn with_min_gap(mut self, gap: u32) -> Self {
+        self.min_gap = gap as i32;
+        self
+    }
+
+    /// Keeps `padding` tiles of rock around rooms made from the template called
+    /// `name`, on top of the minimum gap.
+    pub fn with_padding(mut self, name: &str, padding: u32) -> Self {
+        self.padding.insert(name.to_string(), padding as i32);
+        self.prev_placed = KDTree::default();
+        for placement in &self.placed {
+            let padding = self.padding_of(placement);
+            self.prev_placed.add_padded_rect(placement.rect(), padding);
+        }
+        self
+    }
+
+    /// Decides whether rooms are attached through single entrance tiles or
+    /// through whole sockets.
+    pub fn with_entrances(mut self, entrances: Entrances) -> Self {
+        self.entrances = entrances;
+        self
+    }
+
+    /// Only places rooms that a player moving like `movement` can get into from
+    /// the first room, and that do not cut the player off from any room placed
+    /// before them.
+    pub fn with_movement(mut self, movement: Movement) -> Self {
+        self.movement = Some(movement);
+        self.reached.clear();
+        self.reach = Reach::default();
+        self.checked.take();
+        for placement in self.placed.clone() {
+            self.occupy(&placement);
+            self.record_reach(&placement);
+        }
+        self
+    }
+
+    /// Builds the map around a path that a player can walk from an entrance at
+    /// the top to an exit at the bottom, instead of growing it outwards from a
+    /// room in the middle. The path winds through a grid of `columns` by `rows`
+    /// slots, each holding a single room, and the slots off the path get
+    /// arbitrary rooms. Corridors are not used.
+    pub fn with_solution_path(mut self, columns: u32, rows: u32) -> Self {
+        assert!(columns > 0 && rows > 0, "the grid must not be empty");
+        assert!(
+            self.fixed.is_empty(),
+            "fixed rooms cannot be combined with a solution path"
+        );
+        self.slot_grid = Some((columns, rows));
+        self.solution = None;
+        self.room_stack.clear();
+        self.placed.clear();
+        self.prev_placed = KDTree::default();
+        self.occupied.clear();
+        self.reached.clear();
+        self.reach = Reach::default();
+        self.checked.take();
+        self
+    }
+
+    /// Places `room` by hand with its top left corner at `pos`, before any room
+    /// is generated. Rooms are attached to its exits like to any other, but it
+    /// is never replaced to close a loop. It is placed even if it overlaps
+    /// another room or a forbidden zone.
+    pub fn with_fixed_room(mut self, room: Room, pos: Point2<i32>) -> Self {
+        assert!(
+            self.slot_grid.is_none(),
+            "fixed rooms cannot be combined with a solution path"
+        );
+        let id = self.placed.len();
+        self.fixed.push(id);
+        self.push_placement(room.place(pos, id, None));
+        self
+    }
+
+    /// Keeps rooms out of `rect`, as if it were taken up by a room.
+    pub fn with_forbidden_rect(mut self, rect: Rect) -> Self {
+        self.forbidden.push(rect);
+        self
+    }
+
+    /// Keeps rooms off every tile set in `mask`, as if they were taken up by
+    /// rooms.
+    pub fn with_forbidden_mask(mut self, mask: Mask) -> Self {
+        self.forbidden_masks.push(mask);
+        self
+    }
+
+    /// Grows the map only within the tiles set in `shape`, instead of anywhere
+    /// on the screen, rejecting rooms that leave it. The first room is moved
+    /// to the nearest spot within the shape if it is not already.
+    pub fn with_shape(mut self, shape: Mask) -> Self {
+        self.shape = Some(shape);
+        self.move_into_shape()
+    }
+
+    /// Only places rooms that `region` allows, instead of anywhere on the
+    /// screen.
+    pub(crate) fn with_region(mut self, region: Region) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// Starts the search from `rooms` instead of the first room, giving them
+    /// ids in order. They keep no parents.
+    pub(crate) fn with_rooms(mut self, rooms: Vec<RoomPlacement>) -> Self {
+        self.room_stack.clear();
+        self.placed.clear();
+        self.prev_placed = KDTree::default();
+        self.occupied.clear();
+        self.reached.clear();
+        self.reach = Reach::default();
+        self.checked.take();
+        self.dangling.clear();
+        for (id, mut placement) in rooms.into_iter().enumerate() {
+            placement.id = id;
+            placement.parent = None;
+            placement.joins = None;
+            self.push_placement(placement);
+        }
+        self
+    }
+
+    /// Reports each step of the search to `observer`.
+    pub fn with_observer<P: Observer>(self, observer: P) -> MapGenerator<R, P> {
+        MapGenerator {
+            width: self.width,
+            height: self.height,
+            room_stack: self.room_stack,
+            prev_placed: self.prev_placed,
+            placed: self.placed,
+            target_loops: self.target_loops,
+            connectors: self.connectors,
+            doors: self.doors,
+            corridors: self.corridors,
+            edge_matching: self.edge_matching,
+            collision: self.collision,
+            occupied: self.occupied,
+            min_gap: self.min_gap,
+            padding: self.padding,
+            entrances: self.entrances,
+            movement: self.movement,
+            reached: self.reached,
+            reach: self.reach,
+            checked: self.checked,
+            slot_grid: self.slot_grid,
+            solution: self.solution,
+            region: self.region,
+            fixed: self.fixed,
+            forbidden: self.forbidden,
+            forbidden_masks: self.forbidden_masks,
+            shape: self.shape,
+            dangling: self.dangling,
+            rng: self.rng,
+            observer,
+            started: self.started,
+            finished: self.finished,
+        }
+    }
+
+    pub fn observer(&self) -> &O {
+        &self.observer
+    }
+
+    pub fn observer_mut(&mut self) -> &mut O {
+        &mut self.observer
+    }
+
+    /// Rooms that have been placed but whose exits have not been tried yet, in
+    /// the order they will be returned from last to first.
+    pub fn frontier(&self) -> &[RoomPlacement] {
+        &self.room_stack
+    }
+
+    /// Ids of the rooms on the solution path from the entrance to the exit. Empty
+    /// until the first room has been returned, or if no path could be laid out.
+    pub fn solution_path(&self) -> &[usize] {
+        self.solution.as_deref().unwrap_or(&[])
+    }
+
+    /// Every room placed so far, indexed by id. Unlike the rooms returned by the
+    /// iterator, this reflects dead ends that have been replaced by connectors.
+    pub fn placements(&self) -> &[RoomPlacement] {
+        &self.placed
+    }
+
+    fn screen(&self) -> Rect {
+        Rect {
+            min: Point2::new(0_i32, 0),
+            max: Point2::new(self.width as i32 + 20, self.height as i32 + 20),
+        }
+    }
+
+    /// Why a room covering `r` does not lie where rooms may be placed, if it
+    /// does not.
+    fn out_of_bounds(&self, r: &Rect) -> Option<RejectReason> {
+        match (&self.region, &self.shape) {
+            (Some(region), _) if !region.allows(r) => Some(RejectReason::OutsideMap),
+            (None, Some(shape)) if !shape.covers(r) => Some(RejectReason::OutsideShape),
+            (None, None) if !self.screen().overlaps(r) => Some(RejectReason::OutsideMap),
+            _ => None,
+        }
+    }
+
+    /// Why `placement` cannot be attached to `parent`, if it cannot.
+    fn rejection(&self, placement: &RoomPlacement, parent: &RoomPlacement) -> Option<RejectReason> {
+        let gap = self.min_gap + self.padding_of(placement);
+        if self.collides(placement) {
+            Some(RejectReason::Overlap)
+        } else if (gap > 0 || !self.padding.is_empty())
+            && self
+                .prev_placed
+                .too_close(&placement.rect(), gap, &parent.rect())
+        {
+            Some(RejectReason::TooClose)
+        } else if let Some(reason) = self.out_of_bounds(&placement.rect()) {
+            Some(reason)
+        } else if self.unreachable(placement, parent) {
+            Some(RejectReason::Unreachable)
+        } else {
+            None
+        }
+    }
+
+    /// Rock to keep around `placement` on top of the minimum gap.
+    fn padding_of(&self, placement: &RoomPlacement) -> i32 {
+        self.padding.get(&placement.room.name).copied().unwrap_or(0)
+    }
+
+    /// Pushes the room to the stack and adds it to the kd-tree.
+    fn push_placement(&mut self, placement: RoomPlacement) {
+        let padding = self.padding_of(&placement);
+        self.prev_placed.add_padded_rect(placement.rect(), padding);
+        self.occupy(&placement);
+        self.record_reach(&placement);
+        self.placed.push(placement.clone());
+        self.room_stack.push(placement);
     }
 
     /// Picks a room at random and places it, avoiding overlapping with any previously
@@ -222,11 +698,7 @@ impl<R: Rng> MapGenerator<R> {
         // Try to attach a room to each of the entrances.
         // Create a random order of all of the available rooms and try them one-by-one
         // until one of them fits.
-        let screen = Rect {
-            min: Point2::new(0_i32, 0),
-            max: Point2::new(self.width as i32 + 20, self.height as i32 + 20),
-        };
-        let mut indices: Vec<usize> = (0..AVAILABLE_ROOMS.len()).collect();
+        let mut indices: Vec<usize> = (0..self.templates().len()).collect();
         let mut cardinals = [
             Direction::North,
             Direction::East,
@@ -235,113 +707,290 @@ impl<R: Rng> MapGenerator<R> {
         ];
         cardinals.shuffle(&mut self.rng);
         for cardinal in &cardinals {
-            let mut exits = curr.room.entrances[*cardinal as usize].clone();
+            let mut exits = self.openings(&curr.room, *cardinal);
             exits.shuffle(&mut self.rng);
-            'next_exit: for exit in exits {
+            'next_exit: for exit_socket in exits {
+                let exit = exit_socket.start;
+                self.observer
+                    .on_exit_tried(curr, *cardinal, curr.entrance_tile(*cardinal, exit));
                 indices.shuffle(&mut self.rng);
-                for i in &indices {
+                // The room that fits with the fewest mismatched edge tiles.
+                let mut best: Option<(usize, RoomPlacement)> = None;
+                // Whether candidates left the shape, and whether any were
+                // turned down for anything else.
+                let (mut left_shape, mut blocked) = (false, false);
+                'next_room: for i in &indices {
                     // We have an exit, try the room.
-                    let try_room = &AVAILABLE_ROOMS[*i];
-                    for entrance in &try_room.entrances[cardinal.flip() as usize] {
-                        let attempt_pos = match cardinal {
-                            Direction::North => {
-                                Point2::new(curr.pos.x + exit, curr.pos.y)
-                                    + Vector2::new(-entrance, -(try_room.height as i32))
-                            }
-                            Direction::East => {
-                                Point2::new(curr.pos.x + curr.room.width as i32, curr.pos.y + exit)
-                                    + Vector2::new(0, -entrance)
-                            }
-                            Direction::South => {
-                                Point2::new(curr.pos.x + exit, curr.pos.y + curr.room.height as i32)
-                                    + Vector2::new(-entrance, 0)
-                            }
-                            Direction::West => {
-                                Point2::new(curr.pos.x, curr.pos.y + exit)
-                                    + Vector2::new(-(try_room.width as i32), -entrance)
+                    let try_room = &self.templates()[*i];
+                    for entrance in self.openings(try_room, cardinal.flip()) {
+                        if !exit_socket.fits(&entrance) {
+                            continue;
+                        }
+                        let attempt_pos =
+                            curr.attach_pos(*cardinal, exit, try_room, entrance.start);
+                        let placement =
+                            try_room.place(attempt_pos, self.placed.len(), Some(curr.id));
+                        let r = placement.rect();
+                        if let Some(reason) = self.rejection(&placement, curr) {
+                            self.observer.on_candidate_rejected(&r, reason);
+                            match reason {
+                                RejectReason::OutsideShape => left_shape = true,
+                                _ => blocked = true,
                             }
-                        };
-                        let r = Rect {
-                            min: attempt_pos,
-                            max: attempt_pos
-                                + Vector2::new(try_room.width as i32, try_room.height as i32),
-                        };
-                        if !self.prev_placed.overlaps(&r) && screen.overlaps(&r) {
-                            // Push the room to the stack and add it to the kd-tree.
-                            self.prev_placed.add_rect(r);
-                            self.room_stack.push(try_room.place(attempt_pos));
-                            continue 'next_exit;
+                            continue;
+                        }
+                        let mismatches = self.mismatches(curr, *cardinal, &placement);
+                        if mismatches > 0 && self.edge_matching == EdgeMatching::Strict {
+                            self.observer
+                                .on_candidate_rejected(&r, RejectReason::EdgeMismatch);
+                            blocked = true;
+                            continue;
+                        }
+                        if best.as_ref().is_none_or(|(m, _)| mismatches < *m) {
+                            best = Some((mismatches, placement));
+                        }
+                        if mismatches == 0 {
+                            break 'next_room;
                         }
                     }
                 }
+                if let Some((_, placement)) = best {
+                    self.observer
+                        .on_placed(&placement, curr, curr.entrance_tile(*cardinal, exit));
+                    self.push_placement(placement);
+                    continue 'next_exit;
+                }
+                if !self.corridors.is_empty()
+                    && self.place_via_corridor(curr, *cardinal, &exit_socket)
+                {
+                    continue 'next_exit;
+                }
+                if left_shape && !blocked {
+                    self.dangling.push(DanglingExit {
+                        room: curr.id,
+                        side: *cardinal,
+                        tile: curr.entrance_tile(*cardinal, exit),
+                    });
+                }
             }
         }
+        self.observer.on_room_exhausted(curr);
     }
 }
 
 /// MapGenerator is an iterator of RoomPlacements.
-impl<R: Rng> std::iter::Iterator for MapGenerator<R> {
+impl<R: Rng, O: Observer> std::iter::Iterator for MapGenerator<R, O> {
     type Item = RoomPlacement;
 
     fn next(&mut self) -> Option<RoomPlacement> {
+        if let (Some((columns, rows)), None) = (self.slot_grid, &self.solution) {
+            self.solution = Some(self.lay_out_slots(columns, rows));
+        }
         let curr_room = match self.room_stack.pop() {
             Some(room) => room,
-            // No rooms left, the search has terminated.
-            None => return None,
+            // No rooms left, the search has terminated. Close any loops, apply
+            // the door policy and return the rooms that changed.
+            None => {
+                if self.connectors.is_none() {
+                    let connectors = self.close_loops();
+                    // Popped after the connectors, which may have changed too.
+                    let mut pending = self.apply_doors();
+                    pending.extend(connectors);
+                    self.connectors = Some(pending);
+                }
+                let connector = self.connectors.as_mut().unwrap().pop();
+                if connector.is_none() && !self.finished {
+                    self.finished = true;
+                    let stats = MapStats::compute(&self.placed, self.started.elapsed());
+                    self.observer.on_finished(&stats);
+                }
+                return connector;
+            }
         };
-        self.next_placements(&curr_room);
+        match self.slot_grid {
+            // Every slot has already been filled.
+            Some(_) => self.observer.on_room_exhausted(&curr_room),
+            None => self.next_placements(&curr_room),
+        }
         Some(curr_room)
     }
 }
 
+/// The room template called `name` that maps are generated from, if any.
+pub fn template(name: &str) -> Option<&'static Room> {
+    SOCKET_ROOMS.iter().find(|room| room.name == name)
+}
+
+/// Smallest width and height of a map, which fits the first room and every
+/// template.
+pub fn min_map_size() -> u32 {
+    SOCKET_ROOMS
+        .iter()
+        .chain(std::iter::once(&*START_ROOM))
+        .map(|room| room.width.max(room.height))
+        .max()
+        .unwrap()
+}
+
 lazy_static! {
+    /// The room every map starts from, in the middle of the map.
+    static ref START_ROOM: Room = Room::new(
+        "start",
+        &[
+            &[E, E, E, E, E, E, E, E, E, E],
+            &[E, E, E, E, E, E, E, E, E, E],
+            &[E, D, D, D, E, D, D, D, D, E],
+            &[E, D, D, E, E, E, E, D, D, E],
+            &[E, D, E, E, E, E, E, D, E, E],
+            &[E, D, E, E, E, E, E, D, E, E],
+            &[E, D, E, E, E, E, E, D, E, E],
+            &[E, D, D, E, E, E, E, D, E, E],
+            &[E, D, D, D, E, E, E, D, D, E],
+            &[D, D, D, D, E, E, D, D, D, D],
+        ],
+    );
     static ref AVAILABLE_ROOMS: Vec<Room> = vec![
-        Room::new(&[&[D, D, D], &[E, E, E], &[D, D, D],]),
-        Room::new(&[&[E, D, D], &[E, E, E], &[D, D, E],]),
-        Room::new(&[&[D, D, E], &[E, E, E], &[E, D, D],]),
-        Room::new(&[&[D, E, D], &[D, E, D], &[D, E, D],]),
-        Room::new(&[&[E, E, D], &[D, E, D], &[D, E, E],]),
-        Room::new(&[
-            &[E, E, E, E],
-            &[D, E, E, E],
-            &[D, E, E, D],
-            &[D, E, D, D],
-            &[D, E, D, D],
-            &[D, E, D, D],
-            &[D, D, D, D],
-        ]),
-        Room::new(&[
-            &[E, E, E, E, E, D, D, D],
-            &[D, D, D, E, E, D, D, D],
-            &[D, E, E, E, E, E, E, D],
-            &[D, D, D, E, E, D, D, D],
-        ]),
-        Room::new(&[
-            &[E, D, E, D, E, D, E, D, E],
-            &[E, D, D, D, D, D, D, D, E],
-            &[E, D, E, D, E, D, E, D, E],
-        ]),
-        Room::new(&[
-            &[E, E, E, E, E, E, E, E],
-            &[D, D, D, D, D, D, D, D],
-            &[E, D, D, D, D, D, D, E],
-            &[E, D, D, E, E, D, D, E],
-        ]),
-        Room::new(&[
-            &[E, E, E, E, E, E, E, E, E],
-            &[E, D, D, D, D, D, D, D, E],
-            &[E, D, E, D, E, D, E, D, E],
-            &[E, D, D, D, D, D, D, D, E],
-            &[E, D, E, D, E, D, E, D, E],
-            &[E, D, D, D, D, D, D, D, E],
-        ]),
-        Room::new(&[
-            &[D, D, E, E, E, E, E, E, E],
-            &[D, E, E, E, E, D, E, E, E],
-            &[E, E, E, E, D, D, D, E, E],
-            &[E, E, E, E, E, D, D, D, E],
-            &[E, E, E, E, E, D, D, D, D],
-            &[E, E, E, D, D, D, D, D, D],
-        ]),
+        Room::new("horizontal-hall", &[&[D, D, D], &[E, E, E], &[D, D, D],]),
+        Room::new("zigzag-east", &[&[E, D, D], &[E, E, E], &[D, D, E],]),
+        Room::new("zigzag-west", &[&[D, D, E], &[E, E, E], &[E, D, D],]),
+        Room::new("vertical-hall", &[&[D, E, D], &[D, E, D], &[D, E, D],]),
+        Room::new("zigzag-south", &[&[E, E, D], &[D, E, D], &[D, E, E],]),
+        Room::new(
+            "shaft",
+            &[
+                &[E, E, E, E],
+                &[D, E, E, E],
+                &[D, E, E, D],
+                &[D, E, D, D],
+                &[D, E, D, D],
+                &[D, E, D, D],
+                &[D, D, D, D],
+            ]
+        ),
+        Room::new(
+            "chamber",
+            &[
+                &[E, E, E, E, E, D, D, D],
+                &[D, D, D, E, E, D, D, D],
+                &[D, E, E, E, E, E, E, D],
+                &[D, D, D, E, E, D, D, D],
+            ]
+        ),
+        Room::new(
+            "comb",
+            &[
+                &[E, D, E, D, E, D, E, D, E],
+                &[E, D, D, D, D, D, D, D, E],
+                &[E, D, E, D, E, D, E, D, E],
+            ]
+        ),
+        Room::new(
+            "ledge",
+            &[
+                &[E, E, E, E, E, E, E, E],
+                &[D, D, D, D, D, D, D, D],
+                &[E, D, D, D, D, D, D, E],
+                &[E, D, D, E, E, D, D, E],
+            ]
+        )
+        // Only wide open rooms belong on top of the ledge.
+        .with_socket_kind(Direction::North, 0, "large"),
+        Room::new(
+            "grid",
+            &[
+                &[E, E, E, E, E, E, E, E, E],
+                &[E, D, D, D, D, D, D, D, E],
+                &[E, D, E, D, E, D, E, D, E],
+                &[E, D, D, D, D, D, D, D, E],
+                &[E, D, E, D, E, D, E, D, E],
+                &[E, D, D, D, D, D, D, D, E],
+            ]
+        ),
+        Room::new(
+            "slope",
+            &[
+                &[D, D, E, E, E, E, E, E, E],
+                &[D, E, E, E, E, D, E, E, E],
+                &[E, E, E, E, D, D, D, E, E],
+                &[E, E, E, E, E, D, D, D, E],
+                &[E, E, E, E, E, D, D, D, D],
+                &[E, E, E, D, D, D, D, D, D],
+            ]
+        ),
     ];
+    /// Every template plus the ones that only fit onto typed sockets, for when
+    /// whole sockets are attached.
+    static ref SOCKET_ROOMS: Vec<Room> = AVAILABLE_ROOMS
+        .iter()
+        .cloned()
+        .chain(vec![Room::new(
+            "gallery",
+            &[
+                &[E, E, E, E, E, E, E, E],
+                &[E, D, D, E, E, D, D, E],
+                &[E, E, E, E, E, E, E, E],
+            ]
+        )
+        .with_socket_kind(Direction::North, 0, "large")
+        .with_socket_kind(Direction::South, 0, "large")])
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn smallest_map() {
+        let size = min_map_size();
+        assert!(SOCKET_ROOMS
+            .iter()
+            .all(|r| r.width <= size && r.height <= size));
+        let placed: Vec<_> = MapGenerator::new(size, size, StdRng::seed_from_u64(1)).collect();
+        let start = placed[0].rect();
+        assert!(start.min.x >= 0 && start.max.x <= size as i32);
+        assert!(start.min.y >= 0 && start.max.y <= size as i32);
+    }
+
+    #[test]
+    fn rooms_keep_their_distance() {
+        let mut map_gen = MapGenerator::new(96, 96, StdRng::seed_from_u64(1))
+            .with_min_gap(1)
+            .with_padding("chamber", 2);
+        map_gen.by_ref().for_each(drop);
+        let placed = map_gen.placements();
+        assert!(placed.len() > 10);
+        for (i, a) in placed.iter().enumerate() {
+            for b in &placed[i + 1..] {
+                if b.parent == Some(a.id) {
+                    continue;
+                }
+                let gap = 1 + map_gen.padding_of(a) + map_gen.padding_of(b);
+                assert!(!a.rect().padded(gap).overlaps(&b.rect()));
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_rooms_and_forbidden_zones() {
+        let vault = template("grid").unwrap().clone();
+        let lake = Rect::new(Point2::new(10, 10), Point2::new(40, 30));
+        let overlay = Mask::from_fn(96, 96, |pos| pos.x >= 80);
+        let mut map_gen = MapGenerator::new(96, 96, StdRng::seed_from_u64(1))
+            .with_fixed_room(vault, Point2::new(50, 70))
+            .with_forbidden_rect(lake)
+            .with_forbidden_mask(overlay.clone())
+            .with_loops(5);
+        map_gen.by_ref().for_each(drop);
+        let placed = map_gen.placements();
+        assert_eq!(placed[1].pos, Point2::new(50, 70));
+        assert_eq!(placed[1].room.name, "grid");
+        // Rooms grow off the vault as well.
+        assert!(placed.iter().any(|p| p.parent == Some(1)));
+        for placement in placed {
+            assert!(!lake.overlaps(&placement.rect()));
+            assert!(!overlay.overlaps(&placement.rect()));
+        }
+    }
 }
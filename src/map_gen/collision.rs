@@ -0,0 +1,140 @@
+//! Deciding whether a room collides with the rooms already placed.
+use super::*;
+use std::str::FromStr;
+
+/// What counts as two rooms overlapping.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Collision {
+    /// Rooms overlap if their bounding rectangles do.
+    Rect,
+    /// Rooms overlap only if a tile of one lies on a different tile of the
+    /// other, so that rooms can share dirt and interlock their corners.
+    TileMask,
+}
+
+impl FromStr for Collision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rect" => Ok(Self::Rect),
+            "tile-mask" => Ok(Self::TileMask),
+            _ => Err(format!(
+                "unknown collision mode `{}`, expected rect or tile-mask",
+                s
+            )),
+        }
+    }
+}
+
+/// Determines if every tile that `a` and `b` have in common is the same in both.
+pub(super) fn tiles_compatible(a: &RoomPlacement, b: &RoomPlacement) -> bool {
+    let (ra, rb) = (a.rect(), b.rect());
+    (ra.min.y.max(rb.min.y)..ra.max.y.min(rb.max.y)).all(|y| {
+        (ra.min.x.max(rb.min.x)..ra.max.x.min(rb.max.x)).all(|x| {
+            let pos = Point2::new(x, y);
+            a.tile_at(pos) == b.tile_at(pos)
+        })
+    })
+}
+
+impl<R: Rng, O: Observer> MapGenerator<R, O> {
+    /// Determines if `placement` overlaps any placed room, using the KD-tree of
+    /// bounding rectangles first and only then comparing tiles when the
+    /// collision mode allows it. When comparing tiles, a room also collides if
+    /// every one of its tiles is already covered.
+    pub(super) fn collides(&self, placement: &RoomPlacement) -> bool {
+        if self.forbidden(&placement.rect()) {
+            return true;
+        }
+        if !self.prev_placed.overlaps(&placement.rect()) {
+            return false;
+        }
+        match self.collision {
+            Collision::Rect => true,
+            Collision::TileMask => {
+                let mut covers_new_tile = false;
+                for (y, row) in placement.room.layout.iter().enumerate() {
+                    for (x, tile) in row.iter().enumerate() {
+                        let pos = placement.pos + Vector2::new(x as i32, y as i32);
+                        match self.occupied.get(&pos) {
+                            Some(t) if t != tile => return true,
+                            Some(_) => (),
+                            None => covers_new_tile = true,
+                        }
+                    }
+                }
+                // A room lying entirely on top of placed rooms adds nothing, and
+                // allowing it would let the same room be placed over and over.
+                !covers_new_tile
+            }
+        }
+    }
+
+    /// Determines if `r` reaches into a forbidden zone.
+    pub(super) fn forbidden(&self, r: &Rect) -> bool {
+        self.forbidden.iter().any(|f| f.overlaps(r))
+            || self.forbidden_masks.iter().any(|m| m.overlaps(r))
+    }
+
+    /// Records the tiles of a placed room, if tiles are compared or the player's
+    /// movement is checked.
+    pub(super) fn occupy(&mut self, placement: &RoomPlacement) {
+        if self.collision != Collision::TileMask && self.movement.is_none() {
+            return;
+        }
+        for (y, row) in placement.room.layout.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                let pos = placement.pos + Vector2::new(x as i32, y as i32);
+                self.occupied.insert(pos, *tile);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn interlocking_corners() {
+        let stairs = Room::new("stairs", &[&[E, D, D], &[E, E, D], &[E, E, E]]);
+        let flipped = Room::new("flipped", &[&[E, E, E], &[D, E, E], &[D, D, E]]);
+        let a = stairs.place(Point2::new(0, 0), 0, None);
+        // Dirt over dirt in the top right corner.
+        assert!(tiles_compatible(
+            &a,
+            &flipped.place(Point2::new(1, -2), 1, None)
+        ));
+        // Empty over dirt.
+        assert!(!tiles_compatible(
+            &a,
+            &flipped.place(Point2::new(1, -1), 1, None)
+        ));
+
+        let mut map_gen =
+            MapGenerator::new(32, 32, StdRng::seed_from_u64(0)).with_collision(Collision::TileMask);
+        let start = map_gen.placements()[0].clone();
+        let dirt_corner = Room::new("corner", &[&[D, D], &[D, D]]);
+        // The start room's bottom left tile is dirt.
+        let corner = dirt_corner.place(start.pos + Vector2::new(-1, 9), 1, None);
+        assert!(!map_gen.collides(&corner));
+        assert!(map_gen.collides(&start));
+        map_gen.collision = Collision::Rect;
+        assert!(map_gen.collides(&corner));
+    }
+
+    #[test]
+    fn tile_mask_maps_are_consistent() {
+        let mut map_gen =
+            MapGenerator::new(96, 96, StdRng::seed_from_u64(5)).with_collision(Collision::TileMask);
+        map_gen.by_ref().for_each(drop);
+        let placed = map_gen.placements();
+        for (i, a) in placed.iter().enumerate() {
+            for b in &placed[i + 1..] {
+                assert!(tiles_compatible(a, b));
+            }
+        }
+    }
+}
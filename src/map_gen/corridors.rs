@@ -0,0 +1,226 @@
+//! Synthesized corridors for exits that no room can be attached to directly.
+use super::*;
+
+/// Builds the corridor templates: straight corridors running in either axis
+/// and L-shaped corridors turning in every direction, `width` tiles wide. Leg
+/// lengths are powers of two up to and including `max_length`, to keep the
+/// library small.
+pub(super) fn corridor_library(width: u32, max_length: u32) -> Vec<Room> {
+    assert!(width > 0 && max_length > 0, "corridors must not be empty");
+    let mut lengths: Vec<usize> = (0..)
+        .map(|i| 1 << i)
+        .take_while(|l| *l < max_length as usize)
+        .collect();
+    lengths.push(max_length as usize);
+    let width = width as usize;
+
+    let mut library = Vec::new();
+    for &len in &lengths {
+        let horizontal = straight(width, len);
+        library.push(Room::from_layout(
+            format!("corridor-{}x{}-ns", width, len),
+            transpose(&horizontal),
+        ));
+        library.push(Room::from_layout(
+            format!("corridor-{}x{}-we", width, len),
+            horizontal,
+        ));
+        for &other_len in &lengths {
+            let bend = bend(width, len, other_len);
+            let flipped: Vec<Vec<Tile>> = bend.iter().rev().cloned().collect();
+            for (layout, turn) in [(bend, "n"), (flipped, "s")] {
+                let name = |from| format!("bend-{}x{}x{}-{}{}", width, len, other_len, from, turn);
+                library.push(Room::from_layout(name("e"), mirror(&layout)));
+                library.push(Room::from_layout(name("w"), layout));
+            }
+        }
+    }
+    library
+}
+
+/// A corridor running west to east, walled in on the north and south.
+fn straight(width: usize, len: usize) -> Vec<Vec<Tile>> {
+    let mut layout = vec![vec![Tile::Dirt; len]; width + 2];
+    for row in &mut layout[1..=width] {
+        row.iter_mut().for_each(|t| *t = Tile::Empty);
+    }
+    layout
+}
+
+/// A corridor that enters from the west, runs `horizontal_len` tiles east and
+/// then turns north for `vertical_len` tiles.
+fn bend(width: usize, horizontal_len: usize, vertical_len: usize) -> Vec<Vec<Tile>> {
+    let (w, h) = (horizontal_len + width + 1, vertical_len + width + 1);
+    let mut layout = vec![vec![Tile::Dirt; w]; h];
+    for (y, row) in layout.iter_mut().enumerate() {
+        for (x, t) in row.iter_mut().enumerate() {
+            let in_horizontal = y >= vertical_len && y < vertical_len + width && x < w - 1;
+            let in_vertical = x >= horizontal_len && x < horizontal_len + width && y < h - 1;
+            if in_horizontal || in_vertical {
+                *t = Tile::Empty;
+            }
+        }
+    }
+    layout
+}
+
+fn transpose(layout: &[Vec<Tile>]) -> Vec<Vec<Tile>> {
+    (0..layout[0].len())
+        .map(|x| layout.iter().map(|row| row[x]).collect())
+        .collect()
+}
+
+fn mirror(layout: &[Vec<Tile>]) -> Vec<Vec<Tile>> {
+    layout
+        .iter()
+        .map(|row| row.iter().rev().copied().collect())
+        .collect()
+}
+
+impl<R: Rng, O: Observer> MapGenerator<R, O> {
+    /// Tries to bridge the gap between the exit socket and a room with a corridor.
+    /// Both ends of the corridor are held to the edge matching. Returns true if a
+    /// corridor and a room at its far end were placed.
+    pub(super) fn place_via_corridor(
+        &mut self,
+        curr: &RoomPlacement,
+        cardinal: Direction,
+        exit_socket: &Socket,
+    ) -> bool {
+        let exit = exit_socket.start;
+        // Most exits that nothing fits onto face another room directly.
+        let outside = curr.entrance_tile(cardinal, exit) + cardinal.offset();
+        if self.prev_placed.overlaps(&Rect {
+            min: outside,
+            max: outside + Vector2::new(1, 1),
+        }) {
+            return false;
+        }
+        let mut corridors: Vec<usize> = (0..self.corridors.len()).collect();
+        corridors.shuffle(&mut self.rng);
+        // Every way of attaching a corridor to the exit, with the number of
+        // mismatched tiles along the edge it shares with `curr`. Lenient
+        // matching tries the corridors that line up best first.
+        let mut candidates = Vec::new();
+        for c in corridors {
+            let template = &self.corridors[c];
+            for entrance in self.openings(template, cardinal.flip()) {
+                if !exit_socket.fits(&entrance) {
+                    continue;
+                }
+                let corridor_pos = curr.attach_pos(cardinal, exit, template, entrance.start);
+                let corridor = template.place(corridor_pos, self.placed.len(), Some(curr.id));
+                candidates.push((self.mismatches(curr, cardinal, &corridor), corridor));
+            }
+        }
+        if self.edge_matching == EdgeMatching::Lenient {
+            candidates.sort_by_key(|(mismatches, _)| *mismatches);
+        }
+        let mut indices: Vec<usize> = (0..self.templates().len()).collect();
+        for (mismatches, corridor) in candidates {
+            let corridor_rect = corridor.rect();
+            let rejection = self.rejection(&corridor, curr).or_else(|| {
+                (self.edge_matching == EdgeMatching::Strict && mismatches > 0)
+                    .then_some(RejectReason::EdgeMismatch)
+            });
+            if let Some(reason) = rejection {
+                self.observer.on_candidate_rejected(&corridor_rect, reason);
+                continue;
+            }
+            // The room at the far end with the fewest mismatched edge tiles,
+            // along with the side and the exit it is attached through.
+            let mut best: Option<(usize, Direction, i32, RoomPlacement)> = None;
+            'far_end: for far_side in &CARDINALS {
+                if *far_side == cardinal.flip() {
+                    continue;
+                }
+                for far_socket in self.openings(&corridor.room, *far_side) {
+                    let far_exit = far_socket.start;
+                    // Nothing can be attached if the far end runs straight
+                    // into another room.
+                    let outside = corridor.entrance_tile(*far_side, far_exit) + far_side.offset();
+                    if self.prev_placed.overlaps(&Rect {
+                        min: outside,
+                        max: outside + Vector2::new(1, 1),
+                    }) {
+                        continue;
+                    }
+                    indices.shuffle(&mut self.rng);
+                    for i in &indices {
+                        let try_room = &self.templates()[*i];
+                        for room_entrance in self.openings(try_room, far_side.flip()) {
+                            if !far_socket.fits(&room_entrance) {
+                                continue;
+                            }
+                            let room_pos = corridor.attach_pos(
+                                *far_side,
+                                far_exit,
+                                try_room,
+                                room_entrance.start,
+                            );
+                            let room = try_room.place(room_pos, corridor.id + 1, Some(corridor.id));
+                            // The corridor is not in the tree yet, so check
+                            // against it separately.
+                            let r = room.rect();
+                            let rejection = self.rejection(&room, &corridor).or_else(|| {
+                                let collides = corridor_rect.overlaps(&r)
+                                    && (self.collision == Collision::Rect
+                                        || !collision::tiles_compatible(&corridor, &room));
+                                collides.then_some(RejectReason::Overlap)
+                            });
+                            if let Some(reason) = rejection {
+                                self.observer.on_candidate_rejected(&r, reason);
+                                continue;
+                            }
+                            let mismatches = self.mismatches(&corridor, *far_side, &room);
+                            if mismatches > 0 && self.edge_matching == EdgeMatching::Strict {
+                                self.observer
+                                    .on_candidate_rejected(&r, RejectReason::EdgeMismatch);
+                                continue;
+                            }
+                            if best.as_ref().is_none_or(|(m, ..)| mismatches < *m) {
+                                best = Some((mismatches, *far_side, far_exit, room));
+                            }
+                            if mismatches == 0 {
+                                break 'far_end;
+                            }
+                        }
+                    }
+                }
+            }
+            if let Some((_, far_side, far_exit, room)) = best {
+                self.observer
+                    .on_placed(&corridor, curr, curr.entrance_tile(cardinal, exit));
+                self.observer.on_placed(
+                    &room,
+                    &corridor,
+                    corridor.entrance_tile(far_side, far_exit),
+                );
+                self.push_placement(corridor);
+                self.push_placement(room);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn library_shapes() {
+        let library = corridor_library(2, 4);
+        // Lengths 1, 2 and 4, two straight corridors and four bends per pair of
+        // lengths.
+        assert_eq!(library.len(), 3 * 2 + 3 * 3 * 4);
+        for room in &library {
+            let open_sides = room.entrances.iter().filter(|e| !e.is_empty()).count();
+            assert_eq!(open_sides, 2);
+            for entrances in room.entrances.iter().filter(|e| !e.is_empty()) {
+                assert_eq!(entrances.len(), 2);
+            }
+        }
+    }
+}
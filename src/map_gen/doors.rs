@@ -0,0 +1,20 @@
+//! Opening or sealing doors between rooms that end up touching by accident.
+use super::*;
+use crate::adjacency::{self, DoorPolicy};
+
+impl<R: Rng, O: Observer> MapGenerator<R, O> {
+    /// Applies the door policy to the finished map, returning the rooms that
+    /// changed so that they can be returned again.
+    pub(super) fn apply_doors(&mut self) -> Vec<RoomPlacement> {
+        let policy: DoorPolicy = match self.doors {
+            Some(policy) => policy,
+            None => return Vec::new(),
+        };
+        let edges = adjacency::shared_edges(&self.placed);
+        let changes = adjacency::apply_policy(&edges, policy);
+        adjacency::apply_changes(&mut self.placed, &changes)
+            .into_iter()
+            .map(|i| self.placed[i].clone())
+            .collect()
+    }
+}
@@ -0,0 +1,145 @@
+//! Comparing the whole edge along which a room is attached to its parent.
+use super::*;
+use std::str::FromStr;
+
+/// How the border tiles that a room shares with its parent have to line up.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EdgeMatching {
+    /// Only the exit and the entrance it is attached through have to line up.
+    Any,
+    /// Every empty tile along the shared edge has to face an empty tile.
+    Strict,
+    /// Of the rooms that fit an exit, the one with the fewest empty tiles facing
+    /// dirt along the shared edge is placed.
+    Lenient,
+}
+
+impl FromStr for EdgeMatching {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(Self::Any),
+            "strict" => Ok(Self::Strict),
+            "lenient" => Ok(Self::Lenient),
+            _ => Err(format!(
+                "unknown edge matching `{}`, expected any, strict or lenient",
+                s
+            )),
+        }
+    }
+}
+
+/// Number of tiles along the edge that `child` shares with the `side` edge of
+/// `parent` where an empty tile faces a dirt tile.
+pub(super) fn edge_mismatches(
+    parent: &RoomPlacement,
+    side: Direction,
+    child: &RoomPlacement,
+) -> usize {
+    let (p, c) = (parent.rect(), child.rect());
+    let span = match side {
+        Direction::North | Direction::South => p.min.x.max(c.min.x)..p.max.x.min(c.max.x),
+        Direction::East | Direction::West => p.min.y.max(c.min.y)..p.max.y.min(c.max.y),
+    };
+    // The parent's and the child's border tile at a coordinate along the edge.
+    let facing = |i: i32| match side {
+        Direction::North => (Point2::new(i, p.min.y), Point2::new(i, c.max.y - 1)),
+        Direction::South => (Point2::new(i, p.max.y - 1), Point2::new(i, c.min.y)),
+        Direction::East => (Point2::new(p.max.x - 1, i), Point2::new(c.min.x, i)),
+        Direction::West => (Point2::new(p.min.x, i), Point2::new(c.max.x - 1, i)),
+    };
+    span.filter(|&i| {
+        let (a, b) = facing(i);
+        parent.tile_at(a).is_empty() != child.tile_at(b).is_empty()
+    })
+    .count()
+}
+
+impl<R: Rng, O: Observer> MapGenerator<R, O> {
+    /// Mismatched tiles along the edge that `child` shares with the `side` edge
+    /// of `parent`, or always zero if edges do not have to match.
+    pub(super) fn mismatches(
+        &self,
+        parent: &RoomPlacement,
+        side: Direction,
+        child: &RoomPlacement,
+    ) -> usize {
+        match self.edge_matching {
+            EdgeMatching::Any => 0,
+            _ => edge_mismatches(parent, side, child),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn counts_facing_tiles() {
+        let parent =
+            Room::new("parent", &[&[E, D, E, E], &[D, D, D, D]]).place(Point2::new(0, 0), 0, None);
+        // Attached through the parent's first tile, offset by one to the west.
+        let child =
+            Room::new("child", &[&[D, D, D], &[D, E, D]]).place(Point2::new(-1, -2), 1, Some(0));
+        // x = 0 lines up, x = 1 is dirt on both sides.
+        assert_eq!(edge_mismatches(&parent, Direction::North, &child), 0);
+        let child =
+            Room::new("child", &[&[D, D, D], &[E, E, D]]).place(Point2::new(0, -2), 1, Some(0));
+        // Dirt faces empty at x = 1 and empty faces dirt at x = 2.
+        assert_eq!(edge_mismatches(&parent, Direction::North, &child), 2);
+        let east = Room::new("east", &[&[E, D], &[E, D]]).place(Point2::new(4, 0), 1, Some(0));
+        assert_eq!(edge_mismatches(&parent, Direction::East, &east), 1);
+    }
+
+    /// Side of `parent` that `child` lies against.
+    fn side_of(parent: &RoomPlacement, child: &RoomPlacement) -> Direction {
+        let (p, c) = (parent.rect(), child.rect());
+        if c.max.y == p.min.y {
+            Direction::North
+        } else if c.min.y == p.max.y {
+            Direction::South
+        } else if c.min.x == p.max.x {
+            Direction::East
+        } else {
+            Direction::West
+        }
+    }
+
+    #[test]
+    fn strict_maps_match() {
+        let mut map_gen = MapGenerator::new(96, 96, StdRng::seed_from_u64(3))
+            .with_edge_matching(EdgeMatching::Strict);
+        map_gen.by_ref().for_each(drop);
+        let placed = map_gen.placements();
+        assert!(placed.len() > 10);
+        for child in &placed[1..] {
+            let parent = &placed[child.parent.unwrap()];
+            assert_eq!(edge_mismatches(parent, side_of(parent, child), child), 0);
+        }
+    }
+
+    #[test]
+    fn strict_corridors_and_connectors_match() {
+        let mut map_gen = MapGenerator::new(128, 128, StdRng::seed_from_u64(1))
+            .with_edge_matching(EdgeMatching::Strict)
+            .with_corridors(2, 8)
+            .with_loops(5);
+        map_gen.by_ref().for_each(drop);
+        let placed = map_gen.placements();
+        assert!(placed
+            .iter()
+            .any(|p| p.room.name.starts_with("corridor") || p.room.name.starts_with("bend")));
+        assert!(placed.iter().any(|p| p.joins.is_some()));
+        for child in &placed[1..] {
+            let parent = &placed[child.parent.unwrap()];
+            assert_eq!(edge_mismatches(parent, side_of(parent, child), child), 0);
+            if let Some(joins) = child.joins {
+                let other = &placed[joins];
+                assert_eq!(edge_mismatches(child, side_of(child, other), other), 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Generating a map a little at a time, or on another thread, so that large
+//! maps do not block the caller until they are finished.
+use super::*;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How much work a call to `MapGenerator::step_for` may do.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Budget {
+    /// Keep returning rooms until this much time has passed. A single step can
+    /// take longer, such as closing every loop at the end.
+    Time(Duration),
+    /// Return at most this many rooms.
+    Steps(usize),
+}
+
+impl From<Duration> for Budget {
+    fn from(duration: Duration) -> Self {
+        Self::Time(duration)
+    }
+}
+
+impl From<usize> for Budget {
+    fn from(steps: usize) -> Self {
+        Self::Steps(steps)
+    }
+}
+
+/// What a call to `MapGenerator::step_for` did.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    /// The rooms returned, in the order the iterator would return them.
+    pub rooms: Vec<RoomPlacement>,
+    /// Number of rooms placed so far, including those not returned yet.
+    pub placed: usize,
+    /// Number of rooms whose exits have not been tried yet.
+    pub frontier: usize,
+    /// Whether every room has been returned.
+    pub finished: bool,
+}
+
+impl<R: Rng, O: Observer> MapGenerator<R, O> {
+    /// Returns rooms until `budget`, either a `Duration` or a number of rooms,
+    /// runs out or the map is finished, so that a map can be generated over
+    /// several frames.
+    pub fn step_for(&mut self, budget: impl Into<Budget>) -> Progress {
+        let budget = budget.into();
+        let started = Instant::now();
+        let mut rooms = Vec::new();
+        let mut finished = false;
+        loop {
+            let spent = match budget {
+                Budget::Time(duration) => started.elapsed() >= duration,
+                Budget::Steps(steps) => rooms.len() >= steps,
+            };
+            if spent {
+                break;
+            }
+            match self.next() {
+                Some(room) => rooms.push(room),
+                None => {
+                    finished = true;
+                    break;
+                }
+            }
+        }
+        Progress {
+            rooms,
+            placed: self.placed.len(),
+            frontier: self.room_stack.len(),
+            finished,
+        }
+    }
+}
+
+impl<R, O> MapGenerator<R, O>
+where
+    R: Rng + Send + 'static,
+    O: Observer + Send + 'static,
+{
+    /// Generates the map on a new thread, sending every room down the returned
+    /// channel as it is returned. The thread stops early if the receiver is
+    /// dropped, and hands the generator back once it is done, for its
+    /// placements and observer.
+    pub fn spawn(mut self) -> (Receiver<RoomPlacement>, JoinHandle<Self>) {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            for room in self.by_ref() {
+                if sender.send(room).is_err() {
+                    break;
+                }
+            }
+            self
+        });
+        (receiver, handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    /// Where each room is and what it is.
+    fn layout(rooms: &[RoomPlacement]) -> Vec<(Point2<i32>, String)> {
+        rooms.iter().map(|p| (p.pos, p.room.name.clone())).collect()
+    }
+
+    #[test]
+    fn steps_return_the_same_rooms() {
+        let all: Vec<_> = MapGenerator::new(64, 64, StdRng::seed_from_u64(5)).collect();
+        let mut map_gen = MapGenerator::new(64, 64, StdRng::seed_from_u64(5));
+        let mut stepped = Vec::new();
+        loop {
+            let progress = map_gen.step_for(10);
+            assert!(progress.rooms.len() <= 10);
+            assert!(progress.placed >= stepped.len() + progress.rooms.len());
+            stepped.extend(progress.rooms);
+            if progress.finished {
+                assert_eq!(progress.frontier, 0);
+                break;
+            }
+        }
+        assert_eq!(layout(&stepped), layout(&all));
+
+        let mut map_gen = MapGenerator::new(64, 64, StdRng::seed_from_u64(5));
+        let progress = map_gen.step_for(Duration::from_secs(60));
+        assert!(progress.finished);
+        assert_eq!(layout(&progress.rooms), layout(&all));
+    }
+
+    #[test]
+    fn rooms_stream_from_another_thread() {
+        let all: Vec<_> = MapGenerator::new(64, 64, StdRng::seed_from_u64(6)).collect();
+        let (receiver, handle) = MapGenerator::new(64, 64, StdRng::seed_from_u64(6)).spawn();
+        let streamed: Vec<_> = receiver.iter().collect();
+        assert_eq!(layout(&streamed), layout(&all));
+        assert_eq!(handle.join().unwrap().placements().len(), all.len());
+    }
+}
@@ -0,0 +1,318 @@
+//! Closing loops between separate branches of a generated map.
+use super::*;
+use std::collections::HashSet;
+
+/// Fewest rooms a loop may go through, counting the connector. Anything shorter
+/// is barely distinguishable from a single room with two doors.
+const MIN_LOOP_LENGTH: usize = 5;
+
+impl<R: Rng, O: Observer> MapGenerator<R, O> {
+    /// Replaces dead ends with connectors that are attached to the same parent
+    /// but also open into a room on a different branch, until `target_loops` loops
+    /// have been closed or there are no dead ends left to try.
+    ///
+    /// Connectors are not inserted between rooms in addition to the map. The
+    /// depth-first search has already tried every room at every free exit, so
+    /// there is no space left to put a connector anywhere that is still empty.
+    /// Dead ends are the only rooms that can be taken out again without
+    /// disconnecting anything, so each connector replaces one that has already
+    /// been returned. Connectors take over the id of the dead end they replace,
+    /// and are held to the edge matching on the edges they share with both
+    /// their parent and the room they open into. Rooms without a parent are
+    /// never replaced.
+    pub(super) fn close_loops(&mut self) -> Vec<RoomPlacement> {
+        let mut connectors = Vec::new();
+        if self.target_loops == 0 {
+            return connectors;
+        }
+        // Rooms with children or that a connector opens into have to stay put.
+        let mut keep: HashSet<usize> = self.placed.iter().filter_map(|p| p.parent).collect();
+        // As do the end of the solution path and the rooms placed by hand.
+        keep.extend(self.solution_path().last());
+        keep.extend(&self.fixed);
+        let mut dead_ends: Vec<usize> = (1..self.placed.len())
+            .filter(|id| !keep.contains(id))
+            .collect();
+        dead_ends.shuffle(&mut self.rng);
+
+        let mut borders = HashMap::new();
+        for placement in &self.placed {
+            add_border(&mut borders, placement);
+        }
+        let mut indices: Vec<usize> = (0..self.templates().len()).collect();
+
+        for dead_end in dead_ends {
+            if keep.contains(&dead_end) {
+                continue;
+            }
+            let removed = self.placed[dead_end].clone();
+            // Rooms placed as they are, such as the first one, have nothing to
+            // attach a connector to.
+            let parent = match removed.parent {
+                Some(parent) => self.placed[parent].clone(),
+                None => continue,
+            };
+            // Take the dead end out of the tree while trying connectors in its
+            // place.
+            self.prev_placed.remove_rect(&removed.rect());
+            // The connector with the fewest mismatched edge tiles, along with
+            // the side and the exit of the parent it is attached through.
+            let mut best: Option<(usize, RoomPlacement, Direction, i32)> = None;
+            'candidates: for cardinal in &CARDINALS {
+                for exit_socket in self.openings(&parent.room, *cardinal) {
+                    let exit = exit_socket.start;
+                    let outside = parent.entrance_tile(*cardinal, exit) + cardinal.offset();
+                    if self.prev_placed.overlaps(&Rect {
+                        min: outside,
+                        max: outside + Vector2::new(1, 1),
+                    }) {
+                        continue;
+                    }
+                    indices.shuffle(&mut self.rng);
+                    for i in &indices {
+                        let try_room = &self.templates()[*i];
+                        for entrance in self.openings(try_room, cardinal.flip()) {
+                            if !exit_socket.fits(&entrance) {
+                                continue;
+                            }
+                            let attempt_pos =
+                                parent.attach_pos(*cardinal, exit, try_room, entrance.start);
+                            if attempt_pos == removed.pos && try_room.layout == removed.room.layout
+                            {
+                                continue;
+                            }
+                            let mut connector =
+                                try_room.place(attempt_pos, dead_end, Some(parent.id));
+                            let r = connector.rect();
+                            if self.prev_placed.overlaps(&r) || self.forbidden(&r) {
+                                self.observer
+                                    .on_candidate_rejected(&r, RejectReason::Overlap);
+                                continue;
+                            }
+                            if let Some(reason) = self.out_of_bounds(&r) {
+                                self.observer.on_candidate_rejected(&r, reason);
+                                continue;
+                            }
+                            if self.unreachable(&connector, &parent) {
+                                self.observer
+                                    .on_candidate_rejected(&r, RejectReason::Unreachable);
+                                continue;
+                            }
+                            let mismatches = self.mismatches(&parent, *cardinal, &connector);
+                            if mismatches > 0 && self.edge_matching == EdgeMatching::Strict {
+                                self.observer
+                                    .on_candidate_rejected(&r, RejectReason::EdgeMismatch);
+                                continue;
+                            }
+                            let partner = self.find_partner(
+                                &connector,
+                                (cardinal.flip(), entrance.start),
+                                &borders,
+                            );
+                            let (partner, partner_mismatches) = match partner {
+                                Some(partner) => partner,
+                                None => {
+                                    self.observer
+                                        .on_candidate_rejected(&r, RejectReason::NoLoop);
+                                    continue;
+                                }
+                            };
+                            let mismatches = mismatches + partner_mismatches;
+                            if best.as_ref().is_none_or(|(m, ..)| mismatches < *m) {
+                                connector.joins = Some(partner);
+                                best = Some((mismatches, connector, *cardinal, exit));
+                            }
+                            if mismatches == 0 {
+                                break 'candidates;
+                            }
+                        }
+                    }
+                }
+            }
+            let (connector, cardinal, exit) = match best {
+                Some((_, connector, cardinal, exit)) => (connector, cardinal, exit),
+                None => {
+                    let padding = self.padding_of(&removed);
+                    self.prev_placed.add_padded_rect(removed.rect(), padding);
+                    continue;
+                }
+            };
+            let padding = self.padding_of(&connector);
+            self.prev_placed.add_padded_rect(connector.rect(), padding);
+            keep.extend(connector.joins);
+            borders.retain(|_, id| *id != dead_end);
+            add_border(&mut borders, &connector);
+            self.observer
+                .on_placed(&connector, &parent, parent.entrance_tile(cardinal, exit));
+            self.placed[dead_end] = connector.clone();
+            let r = removed.rect();
+            self.occupied.retain(|pos, _| !r.contains(*pos));
+            let overlapping: Vec<usize> = (0..self.placed.len())
+                .filter(|id| self.placed[*id].rect().overlaps(&r))
+                .collect();
+            for id in overlapping {
+                self.occupy(&self.placed[id].clone());
+            }
+            // Taking out the dead end may have cut the player off from anywhere.
+            self.reach = Reach::default();
+            self.checked.take();
+            self.record_reach(&connector);
+            connectors.push(connector);
+            if connectors.len() == self.target_loops {
+                break;
+            }
+        }
+        connectors
+    }
+
+    /// Looks for a room on another branch that one of the connector's entrances,
+    /// other than the one it is attached through, opens into. `attached_through`
+    /// gives the side and the first tile of that entrance. Returns the room along
+    /// with the number of mismatched tiles on the edge it shares with the
+    /// connector.
+    fn find_partner(
+        &self,
+        connector: &RoomPlacement,
+        attached_through: (Direction, i32),
+        borders: &HashMap<Point2<i32>, usize>,
+    ) -> Option<(usize, usize)> {
+        let parent = connector.parent.unwrap();
+        for side in &CARDINALS {
+            for socket in self.openings(&connector.room, *side) {
+                if (*side, socket.start) == attached_through {
+                    continue;
+                }
+                let outside = connector.entrance_tile(*side, socket.start) + side.offset();
+                let other = match borders.get(&outside) {
+                    Some(&id) if id != connector.id => id,
+                    _ => continue,
+                };
+                if !self.placed[other].tile_at(outside).is_empty() {
+                    continue;
+                }
+                // Whole sockets have to line up, not just the first tile.
+                let other_room = &self.placed[other];
+                if self.entrances == Entrances::Sockets
+                    && !self
+                        .openings(&other_room.room, side.flip())
+                        .iter()
+                        .any(|s| {
+                            other_room.entrance_tile(side.flip(), s.start) == outside
+                                && s.fits(&socket)
+                        })
+                {
+                    continue;
+                }
+                let mismatches = self.mismatches(connector, *side, other_room);
+                if mismatches > 0 && self.edge_matching == EdgeMatching::Strict {
+                    continue;
+                }
+                match self.branch_distance(parent, other) {
+                    // The loop goes through every room on the path between the
+                    // two plus the connector itself.
+                    Some(dist) if dist + 2 >= MIN_LOOP_LENGTH => return Some((other, mismatches)),
+                    _ => (),
+                }
+            }
+        }
+        None
+    }
+
+    /// Number of steps through the placement tree between two rooms, or None if
+    /// one is the ancestor of the other.
+    fn branch_distance(&self, a: usize, b: usize) -> Option<usize> {
+        let mut ancestors = HashMap::new();
+        let mut curr = Some(a);
+        let mut steps = 0;
+        while let Some(id) = curr {
+            ancestors.insert(id, steps);
+            curr = self.placed[id].parent;
+            steps += 1;
+        }
+        let mut curr = Some(b);
+        let mut steps = 0;
+        while let Some(id) = curr {
+            if let Some(&a_steps) = ancestors.get(&id) {
+                return if a_steps == 0 || steps == 0 {
+                    None
+                } else {
+                    Some(a_steps + steps)
+                };
+            }
+            curr = self.placed[id].parent;
+            steps += 1;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adjacency;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn closes_loops() {
+        let mut map_gen = MapGenerator::new(128, 128, StdRng::seed_from_u64(1)).with_loops(5);
+        let returned: Vec<_> = map_gen.by_ref().collect();
+        let placed = map_gen.placements();
+        let connectors: Vec<_> = placed.iter().filter(|p| p.joins.is_some()).collect();
+        assert_eq!(connectors.len(), 5);
+        let edges = adjacency::shared_edges(placed);
+        let door_between = |a: usize, b: usize| {
+            edges.iter().any(|e| {
+                (e.a == a && e.b == b || e.a == b && e.b == a) && e.doors().next().is_some()
+            })
+        };
+        for connector in &connectors {
+            // The connector reuses the id of the dead end it replaced.
+            assert_eq!(returned.iter().filter(|p| p.id == connector.id).count(), 2);
+            assert!(door_between(connector.id, connector.parent.unwrap()));
+            assert!(door_between(connector.id, connector.joins.unwrap()));
+        }
+        // A graph has as many independent cycles as it has links more than
+        // rooms, plus one for every connected part.
+        let links: HashSet<(usize, usize)> = placed
+            .iter()
+            .flat_map(|p| p.parent.into_iter().chain(p.joins).map(move |o| (p.id, o)))
+            .map(|(a, b)| (a.min(b), a.max(b)))
+            .collect();
+        let mut part: Vec<usize> = (0..placed.len()).collect();
+        fn find(part: &mut [usize], i: usize) -> usize {
+            if part[i] != i {
+                part[i] = find(part, part[i]);
+            }
+            part[i]
+        }
+        for &(a, b) in &links {
+            let (a, b) = (find(&mut part, a), find(&mut part, b));
+            part[a] = b;
+        }
+        let parts = (0..placed.len())
+            .filter(|&i| find(&mut part, i) == i)
+            .count();
+        assert_eq!(links.len() + parts - placed.len(), 5);
+    }
+
+    #[test]
+    fn rooms_without_parents_stay() {
+        // Neither room has a parent, and nothing can be attached to the second.
+        let rooms = vec![
+            START_ROOM.place(Point2::new(20, 20), 0, None),
+            START_ROOM.place(Point2::new(80, 80), 1, None),
+        ];
+        let mut map_gen = MapGenerator::new(128, 128, StdRng::seed_from_u64(1))
+            .with_forbidden_rect(Rect {
+                min: Point2::new(70, 70),
+                max: Point2::new(100, 100),
+            })
+            .with_rooms(rooms)
+            // More than can be closed, so that every dead end is tried.
+            .with_loops(1000);
+        map_gen.by_ref().for_each(drop);
+        let placed = map_gen.placements();
+        assert_eq!(placed[0].pos, Point2::new(20, 20));
+        assert_eq!(placed[1].pos, Point2::new(80, 80));
+    }
+}
@@ -0,0 +1,359 @@
+//! Building the map around a guaranteed path from an entrance at the top to an
+//! exit at the bottom, in the style of Spelunky.
+//!
+//! The map is split into a coarse grid of slots, and a path is carved through it
+//! from a slot in the top row to one in the bottom row, running sideways along
+//! each row before dropping down to the next. Every slot on the path gets one
+//! room with openings towards the slots before and after it that can be walked
+//! between, attached to the room in the slot before it. If the path cannot be
+//! completed, rooms are taken out again and others tried, and eventually a new
+//! path is carved. Every other slot then gets an arbitrary room attached to a
+//! room in a neighbouring slot, if any fits.
+//!
+//! Templates differ in size, so the grid only decides which rooms are attached
+//! to each other, not where they end up on the map.
+use super::*;
+use std::collections::{HashSet, VecDeque};
+
+/// Rooms tried along a path before carving a different one.
+const PATH_ATTEMPTS: usize = 5000;
+
+/// Paths carved before giving up on the map.
+const PATH_CARVINGS: usize = 20;
+
+/// A path being laid out, one room per slot.
+struct PathSearch {
+    slots: Vec<Point2<i32>>,
+    columns: i32,
+    /// Side and first tile of the opening each room on the path so far was
+    /// entered through.
+    entrances: Vec<(Direction, i32)>,
+    /// Entrance tile of the exit each room on the path so far is attached to.
+    exits: Vec<Point2<i32>>,
+    /// Rooms left to try before giving up on the path.
+    budget: usize,
+}
+
+/// Slots from a random one in the top row to one in the bottom row. The path
+/// runs in a random direction along each row, dropping down at random or at
+/// the edge of the grid, so it never crosses itself.
+pub(super) fn carve_path<R: Rng>(rng: &mut R, columns: i32, rows: i32) -> Vec<Point2<i32>> {
+    let mut slot = Point2::new(rng.gen_range(0, columns), 0);
+    let mut path = vec![slot];
+    loop {
+        let side = if rng.gen() {
+            Direction::East
+        } else {
+            Direction::West
+        };
+        loop {
+            let next = slot + side.offset();
+            if next.x < 0 || next.x >= columns || rng.gen_ratio(1, 3) {
+                break;
+            }
+            slot = next;
+            path.push(slot);
+        }
+        if slot.y == rows - 1 {
+            return path;
+        }
+        slot.y += 1;
+        path.push(slot);
+    }
+}
+
+/// The side of slot `from` that `to` lies on. The slots must be neighbours.
+fn toward(from: Point2<i32>, to: Point2<i32>) -> Direction {
+    *CARDINALS
+        .iter()
+        .find(|side| from + side.offset() == to)
+        .expect("slots are not neighbours")
+}
+
+/// Determines if the border tiles at `a` and `b` of `room`, each given as a
+/// side and an index along it, are joined by empty tiles.
+fn connected(room: &Room, a: (Direction, i32), b: (Direction, i32)) -> bool {
+    let placement = room.place(Point2::new(0, 0), 0, None);
+    let rect = placement.rect();
+    let goal = placement.entrance_tile(b.0, b.1);
+    let mut stack = vec![placement.entrance_tile(a.0, a.1)];
+    let mut seen: HashSet<Point2<i32>> = stack.iter().copied().collect();
+    while let Some(pos) = stack.pop() {
+        if pos == goal {
+            return true;
+        }
+        for side in &CARDINALS {
+            let next = pos + side.offset();
+            if rect.contains(next) && placement.tile_at(next).is_empty() && seen.insert(next) {
+                stack.push(next);
+            }
+        }
+    }
+    false
+}
+
+impl<R: Rng, O: Observer> MapGenerator<R, O> {
+    /// Fills a grid of `columns` by `rows` slots around a path from top to
+    /// bottom. Returns the ids of the rooms on the path in order, or nothing if
+    /// no path could be laid out.
+    pub(super) fn lay_out_slots(&mut self, columns: u32, rows: u32) -> Vec<usize> {
+        for _ in 0..PATH_CARVINGS {
+            let mut search = PathSearch {
+                slots: carve_path(&mut self.rng, columns as i32, rows as i32),
+                columns: columns as i32,
+                entrances: Vec::new(),
+                exits: Vec::new(),
+                budget: PATH_ATTEMPTS,
+            };
+            if !self.lay_path(&mut search) {
+                continue;
+            }
+            for (placement, exit) in self.placed[1..].iter().zip(&search.exits) {
+                let parent = &self.placed[placement.parent.unwrap()];
+                self.observer.on_placed(placement, parent, *exit);
+            }
+            let slots = search.slots;
+            self.fill_slots(&slots, columns as i32, rows as i32);
+            // Hand the rooms out from the entrance onwards.
+            self.room_stack.reverse();
+            return (0..slots.len()).collect();
+        }
+        Vec::new()
+    }
+
+    /// Places a room in the next slot of the path, and recursively in every
+    /// slot after it. Takes the room out again if the rest of the path cannot
+    /// be laid out behind it.
+    fn lay_path(&mut self, search: &mut PathSearch) -> bool {
+        let i = self.placed.len();
+        let slots = &search.slots;
+        if i == slots.len() {
+            return true;
+        }
+        let entered = match i {
+            0 => Direction::North,
+            _ => toward(slots[i], slots[i - 1]),
+        };
+        let left = match slots.get(i + 1) {
+            Some(next) => toward(slots[i], *next),
+            None => Direction::South,
+        };
+        let mut indices: Vec<usize> = (0..self.templates().len()).collect();
+        indices.shuffle(&mut self.rng);
+        for index in indices {
+            let room = &self.templates()[index];
+            let exits = self.openings(room, left);
+            for entrance in self.openings(room, entered) {
+                let through = (entered, entrance.start);
+                if !exits
+                    .iter()
+                    .any(|exit| connected(room, through, (left, exit.start)))
+                {
+                    continue;
+                }
+                for (pos, exit) in self.path_positions(search, room, &entrance) {
+                    if search.budget == 0 {
+                        return false;
+                    }
+                    search.budget -= 1;
+                    let placement = room.place(pos, i, i.checked_sub(1));
+                    if let Some(parent) = self.placed.last() {
+                        if let Some(reason) = self.rejection(&placement, parent) {
+                            self.observer
+                                .on_candidate_rejected(&placement.rect(), reason);
+                            continue;
+                        }
+                    }
+                    self.push_placement(placement);
+                    search.entrances.push(through);
+                    search.exits.extend(exit);
+                    if self.lay_path(search) {
+                        return true;
+                    }
+                    search.entrances.pop();
+                    if exit.is_some() {
+                        search.exits.pop();
+                    }
+                    self.unplace_last();
+                }
+            }
+        }
+        false
+    }
+
+    /// Where `room` can go in the next slot of the path when entered through
+    /// `entrance`, along with the entrance tile of the exit it is attached to.
+    /// The first room goes at the top of the map above the middle of its slot,
+    /// and the others onto any exit of the room before them that can be reached
+    /// from where that room was entered.
+    fn path_positions(
+        &self,
+        search: &PathSearch,
+        room: &Room,
+        entrance: &Socket,
+    ) -> Vec<(Point2<i32>, Option<Point2<i32>>)> {
+        let prev = match self.placed.last() {
+            Some(prev) => prev,
+            None => {
+                let slot = search.slots[0];
+                let x = self.width as i32 * (2 * slot.x + 1) / (2 * search.columns);
+                return vec![(Point2::new(x - room.width as i32 / 2, 0), None)];
+            }
+        };
+        let side = toward(search.slots[prev.id], search.slots[prev.id + 1]);
+        let prev_entrance = search.entrances[prev.id];
+        self.openings(&prev.room, side)
+            .into_iter()
+            .filter(|exit| {
+                exit.fits(entrance) && connected(&prev.room, prev_entrance, (side, exit.start))
+            })
+            .map(|exit| {
+                (
+                    prev.attach_pos(side, exit.start, room, entrance.start),
+                    Some(prev.entrance_tile(side, exit.start)),
+                )
+            })
+            .collect()
+    }
+
+    /// Takes the last room placed back out again.
+    fn unplace_last(&mut self) {
+        let removed = self.placed.pop().unwrap();
+        self.room_stack.pop();
+        self.reached.remove(&removed.id);
+        self.reach = Reach::default();
+        self.checked.take();
+        self.prev_placed = KDTree::default();
+        self.occupied.clear();
+        for placement in self.placed.clone() {
+            let padding = self.padding_of(&placement);
+            self.prev_placed.add_padded_rect(placement.rect(), padding);
+            self.occupy(&placement);
+        }
+    }
+
+    /// Spreads out from the path, attaching an arbitrary room to a room in a
+    /// neighbouring slot for every slot that one fits into. Slots that nothing
+    /// fits into stay solid rock.
+    fn fill_slots(&mut self, path: &[Point2<i32>], columns: i32, rows: i32) {
+        let mut filled: HashMap<Point2<i32>, usize> = path
+            .iter()
+            .enumerate()
+            .map(|(id, slot)| (*slot, id))
+            .collect();
+        let mut queue: VecDeque<Point2<i32>> = path.iter().copied().collect();
+        while let Some(slot) = queue.pop_front() {
+            let mut sides = CARDINALS;
+            sides.shuffle(&mut self.rng);
+            for side in &sides {
+                let next = slot + side.offset();
+                if next.x < 0 || next.x >= columns || next.y < 0 || next.y >= rows {
+                    continue;
+                }
+                if filled.contains_key(&next) {
+                    continue;
+                }
+                let parent = self.placed[filled[&slot]].clone();
+                if let Some(id) = self.fill_slot(&parent, *side) {
+                    filled.insert(next, id);
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    /// Attaches an arbitrary room to any exit on the `side` edge of `parent`.
+    /// Returns the id of the room, if one fits.
+    fn fill_slot(&mut self, parent: &RoomPlacement, side: Direction) -> Option<usize> {
+        let mut exits = self.openings(&parent.room, side);
+        exits.shuffle(&mut self.rng);
+        let mut indices: Vec<usize> = (0..self.templates().len()).collect();
+        for exit in exits {
+            let exit_tile = parent.entrance_tile(side, exit.start);
+            self.observer.on_exit_tried(parent, side, exit_tile);
+            indices.shuffle(&mut self.rng);
+            for i in &indices {
+                let try_room = &self.templates()[*i];
+                for entrance in self.openings(try_room, side.flip()) {
+                    if !exit.fits(&entrance) {
+                        continue;
+                    }
+                    let pos = parent.attach_pos(side, exit.start, try_room, entrance.start);
+                    let placement = try_room.place(pos, self.placed.len(), Some(parent.id));
+                    if let Some(reason) = self.rejection(&placement, parent) {
+                        self.observer
+                            .on_candidate_rejected(&placement.rect(), reason);
+                        continue;
+                    }
+                    self.observer.on_placed(&placement, parent, exit_tile);
+                    let id = placement.id;
+                    self.push_placement(placement);
+                    return Some(id);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text_map::TileMap;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn carved_paths_wind_downwards() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let path = carve_path(&mut rng, 4, 5);
+            assert_eq!(path[0].y, 0);
+            assert_eq!(path.last().unwrap().y, 4);
+            for pair in path.windows(2) {
+                toward(pair[0], pair[1]);
+                assert!(pair[1].y >= pair[0].y);
+            }
+            let unique: HashSet<_> = path.iter().collect();
+            assert_eq!(unique.len(), path.len());
+        }
+    }
+
+    #[test]
+    fn solution_path_leads_from_top_to_bottom() {
+        for seed in 0..5 {
+            let mut map_gen =
+                MapGenerator::new(96, 96, StdRng::seed_from_u64(seed)).with_solution_path(4, 4);
+            let returned = map_gen.by_ref().count();
+            let solution = map_gen.solution_path().to_vec();
+            let placed = map_gen.placements();
+            assert!(solution.len() >= 4);
+            assert!(placed.len() > solution.len());
+            assert_eq!(returned, placed.len());
+
+            // Walk from the entrance at the top of the first room.
+            let map = TileMap::from_placements(placed);
+            let entrance = &placed[solution[0]];
+            let exit = &placed[*solution.last().unwrap()];
+            let mut stack: Vec<_> = entrance.room.entrances[Direction::North as usize]
+                .iter()
+                .map(|i| entrance.entrance_tile(Direction::North, *i))
+                .collect();
+            let mut seen: HashSet<_> = stack.iter().copied().collect();
+            while let Some(pos) = stack.pop() {
+                for side in &CARDINALS {
+                    let next = pos + side.offset();
+                    if map.get(next).is_some_and(|t| t.is_empty()) && seen.insert(next) {
+                        stack.push(next);
+                    }
+                }
+            }
+            assert!(
+                exit.room.entrances[Direction::South as usize]
+                    .iter()
+                    .any(|i| seen.contains(&exit.entrance_tile(Direction::South, *i))),
+                "no way through for seed {}",
+                seed
+            );
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Keeping every room reachable by a player under gravity.
+//!
+//! The player is first only followed around a candidate and the room it is
+//! attached to, starting from where they could stand in the latter, which
+//! quickly turns most unreachable candidates down. Candidates that pass are
+//! checked against the whole map, since a room can also open up a floor that
+//! the way to some other room depended on. The player starts anywhere in the
+//! first room, and rooms placed by hand do not have to be reachable.
+use super::*;
+use crate::reachability;
+
+impl<R: Rng, O: Observer> MapGenerator<R, O> {
+    /// Tiles of `placement` the player can stand on having come from where they
+    /// could stand in `parent`. Empty if reachability is not checked.
+    pub(super) fn reach(
+        &self,
+        placement: &RoomPlacement,
+        parent: &RoomPlacement,
+    ) -> Vec<Point2<i32>> {
+        let movement = match &self.movement {
+            Some(movement) => movement,
+            None => return Vec::new(),
+        };
+        let from = match self.reached.get(&parent.id) {
+            Some(tiles) => tiles.clone(),
+            // A corridor that has not been placed yet.
+            None => self.reach(parent, &self.placed[parent.parent.unwrap()]),
+        };
+        // Far enough around both rooms to jump and fall between them.
+        let margin = movement
+            .jump_height
+            .max(movement.jump_distance)
+            .max(movement.fall_tolerance) as i32;
+        let (r, p) = (placement.rect(), parent.rect());
+        let window = Rect {
+            min: Point2::new(r.min.x.min(p.min.x), r.min.y.min(p.min.y)),
+            max: Point2::new(r.max.x.max(p.max.x), r.max.y.max(p.max.y)),
+        }
+        .padded(margin);
+        let tile_at = |pos| self.tile_with(&[placement, parent], pos);
+        let visited = reachability::reachable(
+            |pos| tile_at(pos).filter(|_| window.contains(pos)),
+            &from,
+            movement,
+        );
+        reachability::standing_tiles(placement, tile_at)
+            .into_iter()
+            .filter(|pos| visited.contains(pos))
+            .collect()
+    }
+
+    /// Determines if the player cannot get into `placement` from `parent`, or
+    /// would no longer survive dropping through a floor that it opens up.
+    pub(super) fn unreachable(&self, placement: &RoomPlacement, parent: &RoomPlacement) -> bool {
+        let movement = match &self.movement {
+            Some(movement) => movement,
+            None => return false,
+        };
+        let tile_at = |pos| self.tile_with(&[placement, parent], pos);
+        let open = |pos| tile_at(pos).is_some_and(|t: Tile| t.is_empty());
+        let r = placement.rect();
+        let opens_fatal_drop = (r.min.x..r.max.x).any(|x| {
+            let mut top = Point2::new(x, r.min.y - 1);
+            if !placement.tile_at(Point2::new(x, r.min.y)).is_empty()
+                || !self.occupied.get(&top).is_some_and(|t| t.is_empty())
+            {
+                return false;
+            }
+            // The player may have been falling from as high up as the column
+            // of empty tiles goes.
+            while open(top - Vector2::new(0, 1)) {
+                top.y -= 1;
+            }
+            reachability::landing(tile_at, top, top.y, movement).is_none()
+        });
+        opens_fatal_drop
+            || self.reach(placement, parent).is_empty()
+            || self.strands_rooms(placement, parent, movement)
+    }
+
+    /// Determines if the player could no longer get into every room, or into
+    /// `placement`, once it is placed. `parent` is placed along with it if it
+    /// has not been yet.
+    fn strands_rooms(
+        &self,
+        placement: &RoomPlacement,
+        parent: &RoomPlacement,
+        movement: &Movement,
+    ) -> bool {
+        let mut pending = vec![placement];
+        if parent.id >= self.placed.len() {
+            pending.push(parent);
+        }
+        let (reach, extends) = self.reach_with(&pending, movement);
+        let stranded = self.stranded(&pending, &reach, extends);
+        if pending.len() == 1 && !stranded {
+            *self.checked.borrow_mut() = Some((placement.clone(), reach, extends));
+        }
+        stranded
+    }
+
+    /// Determines if the player cannot get into every room once `pending` are
+    /// placed as well, according to what `reach_with` made of them.
+    fn stranded(&self, pending: &[&RoomPlacement], reach: &Reach, extends: bool) -> bool {
+        let placement = pending[0];
+        let entered = |p: &RoomPlacement| {
+            let r = p.rect();
+            (r.min.y..r.max.y).any(|y| {
+                (r.min.x..r.max.x).any(|x| {
+                    let pos = Point2::new(x, y);
+                    reach.visited.contains(&pos) || extends && self.reach.visited.contains(&pos)
+                })
+            })
+        };
+        if extends {
+            // Nowhere the player could get to before is cut off.
+            return pending.iter().any(|p| !entered(p));
+        }
+        self.placed
+            .iter()
+            .filter(|p| p.id != placement.id && !self.fixed.contains(&p.id))
+            .chain(pending.iter().copied())
+            .any(|p| !entered(p))
+    }
+
+    /// Where the player can get to once `pending` have been placed as well, the
+    /// first of them in place of the room with its id if there is one. Also
+    /// returns whether that only adds to `self.reach`, in which case only what
+    /// is new is returned.
+    fn reach_with(&self, pending: &[&RoomPlacement], movement: &Movement) -> (Reach, bool) {
+        let replaced = self
+            .placed
+            .get(pending[0].id)
+            .filter(|p| p.pos != pending[0].pos || p.room.layout != pending[0].room.layout)
+            .map(|p| p.rect());
+        let pending_at = |pos| pending.iter().any(|p| p.rect().contains(pos));
+        let tile_at = |pos| match replaced {
+            Some(r) if r.contains(pos) && !pending_at(pos) => None,
+            _ => self.tile_with(pending, pos),
+        };
+        // Placing rooms only adds ways to go, unless they open up a floor that
+        // the player could stand on.
+        let opens_floor = pending.iter().any(|p| {
+            let r = p.rect();
+            (r.min.y..r.max.y).any(|y| {
+                (r.min.x..r.max.x).any(|x| {
+                    let pos = Point2::new(x, y);
+                    let above = pos - Vector2::new(0, 1);
+                    p.tile_at(pos).is_empty()
+                        && !pending_at(above)
+                        && self.reach.visited.contains(&above)
+                })
+            })
+        });
+        if replaced.is_none() && !opens_floor && !self.reach.standing.is_empty() {
+            // Only the player standing near the new rooms can make use of them.
+            let margin = movement.jump_height.max(movement.jump_distance) as i32 + 1;
+            let windows: Vec<Rect> = pending.iter().map(|p| p.rect().padded(margin)).collect();
+            let from: Vec<Point2<i32>> = self
+                .reach
+                .standing
+                .iter()
+                .filter(|pos| windows.iter().any(|w| w.contains(**pos)))
+                .copied()
+                .collect();
+            let reach = reachability::explore(tile_at, &from, movement, &self.reach);
+            return (reach, true);
+        }
+        let start = match self.placed.first() {
+            Some(start) if replaced.is_none() || start.id != pending[0].id => start,
+            _ => pending[0],
+        };
+        let from = reachability::standing_tiles(start, tile_at);
+        let reach = reachability::explore(tile_at, &from, movement, &Reach::default());
+        (reach, false)
+    }
+
+    /// The tile at `pos` once `pending` have been placed as well.
+    fn tile_with(&self, pending: &[&RoomPlacement], pos: Point2<i32>) -> Option<Tile> {
+        match pending.iter().find(|p| p.rect().contains(pos)) {
+            Some(p) => Some(p.tile_at(pos)),
+            None => self.occupied.get(&pos).copied(),
+        }
+    }
+
+    /// Records where the player can stand in a room that has just been placed.
+    pub(super) fn record_reach(&mut self, placement: &RoomPlacement) {
+        if self.movement.is_none() {
+            return;
+        }
+        let tiles = match placement.parent {
+            Some(parent) => self.reach(placement, &self.placed[parent]),
+            None => reachability::standing_tiles(placement, |pos| {
+                placement
+                    .rect()
+                    .contains(pos)
+                    .then(|| placement.tile_at(pos))
+            }),
+        };
+        self.reached.insert(placement.id, tiles);
+        let movement = self.movement.unwrap();
+        let (reach, extends) = match self.checked.borrow_mut().take() {
+            Some((checked, reach, extends))
+                if checked.id == placement.id
+                    && checked.pos == placement.pos
+                    && checked.room.layout == placement.room.layout =>
+            {
+                (reach, extends)
+            }
+            _ => self.reach_with(&[placement], &movement),
+        };
+        if extends {
+            self.reach.visited.extend(reach.visited);
+            self.reach.standing.extend(reach.standing);
+        } else {
+            self.reach = reach;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn platformer_maps_are_reachable() {
+        let movement = Movement::default();
+        let unchecked = MapGenerator::new(96, 96, StdRng::seed_from_u64(4)).collect::<Vec<_>>();
+        let unchecked = reachability::unreachable_rooms(&unchecked, &movement);
+
+        let mut map_gen =
+            MapGenerator::new(96, 96, StdRng::seed_from_u64(4)).with_movement(movement);
+        map_gen.by_ref().for_each(drop);
+        let placed = map_gen.placements();
+        assert!(placed.len() > 10);
+        assert!(reachability::unreachable_rooms(placed, &movement).is_empty());
+        assert!(!unchecked.is_empty());
+    }
+}
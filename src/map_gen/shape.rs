@@ -0,0 +1,98 @@
+//! Growing the map within an arbitrary shape instead of the screen.
+use super::*;
+
+/// An exit left open because every room that would have fit onto it leaves
+/// the shape the map is grown in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DanglingExit {
+    /// Id of the room the exit belongs to.
+    pub room: usize,
+    pub side: Direction,
+    /// Entrance tile of the exit.
+    pub tile: Point2<i32>,
+}
+
+impl<R: Rng, O: Observer> MapGenerator<R, O> {
+    /// Exits that no room could be attached to because the rooms that fit
+    /// leave the shape, and that still open onto solid rock. Empty unless a
+    /// shape is given.
+    pub fn dangling_exits(&self) -> Vec<DanglingExit> {
+        self.dangling
+            .iter()
+            .filter(|exit| {
+                // Loops may have swapped the room for another since.
+                let room = &self.placed[exit.room];
+                let outside = exit.tile + exit.side.offset();
+                room.rect().contains(exit.tile)
+                    && room.tile_at(exit.tile).is_empty()
+                    && !self
+                        .prev_placed
+                        .overlaps(&Rect::new(outside, outside + Vector2::new(1, 1)))
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Moves the first room to the spot nearest to where it is now that lies
+    /// within the shape, if it does not already.
+    pub(super) fn move_into_shape(self) -> Self {
+        let shape = match &self.shape {
+            Some(shape) => shape,
+            None => return self,
+        };
+        let first = match self.placed.first() {
+            Some(first) if first.parent.is_none() && !shape.covers(&first.rect()) => first,
+            _ => return self,
+        };
+        let size = first.rect().max - first.rect().min;
+        let spot = (0..shape.height() as i32)
+            .flat_map(|y| (0..shape.width() as i32).map(move |x| Point2::new(x, y)))
+            .filter(|pos| shape.covers(&Rect::new(*pos, *pos + size)))
+            .min_by_key(|pos| {
+                let d = pos - first.pos;
+                d.x * d.x + d.y * d.y
+            });
+        match spot {
+            Some(pos) => {
+                let mut rooms = self.placed.clone();
+                rooms[0].pos = pos;
+                self.with_rooms(rooms)
+            }
+            None => self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn rooms_stay_within_the_shape() {
+        let circle = Mask::ellipse(96, 96);
+        let mut map_gen =
+            MapGenerator::new(96, 96, StdRng::seed_from_u64(2)).with_shape(circle.clone());
+        map_gen.by_ref().for_each(drop);
+        assert!(map_gen.placements().len() > 10);
+        assert!(map_gen
+            .placements()
+            .iter()
+            .all(|p| circle.covers(&p.rect())));
+        let dangling = map_gen.dangling_exits();
+        assert!(!dangling.is_empty());
+        assert!(dangling
+            .iter()
+            .all(|exit| map_gen.placements()[exit.room].rect().contains(exit.tile)));
+
+        // The first room starts out in the middle of the map, outside a strip
+        // along its west edge.
+        let strip = Mask::from_fn(96, 96, |pos| pos.x < 30);
+        let mut map_gen =
+            MapGenerator::new(96, 96, StdRng::seed_from_u64(2)).with_shape(strip.clone());
+        assert!(strip.covers(&map_gen.placements()[0].rect()));
+        map_gen.by_ref().for_each(drop);
+        assert!(map_gen.placements().len() > 1);
+        assert!(map_gen.placements().iter().all(|p| strip.covers(&p.rect())));
+    }
+}
@@ -0,0 +1,198 @@
+//! Attaching rooms through whole spans of entrance tiles at once.
+use super::*;
+use std::str::FromStr;
+
+/// A contiguous span of entrance tiles along one edge of a room.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Socket {
+    /// Index of the first tile of the span along the edge.
+    pub start: i32,
+    /// Number of tiles in the span.
+    pub width: i32,
+    /// What the socket is meant for, such as `corridor` or `water`. Untyped
+    /// sockets only fit other untyped sockets.
+    pub kind: Option<String>,
+}
+
+impl Socket {
+    /// Determines if a room can be attached through `other` onto this socket.
+    /// Types have to be equal, so an untyped socket does not fit a typed one
+    /// either way.
+    pub fn fits(&self, other: &Socket) -> bool {
+        self.width == other.width && self.kind == other.kind
+    }
+}
+
+/// What rooms are attached through.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Entrances {
+    /// Every entrance tile on its own, so that a room may be attached to a
+    /// single tile of a wider opening. Socket types are ignored.
+    Tiles,
+    /// Whole sockets, each onto a socket of the same width and type. Untyped
+    /// sockets only fit untyped ones. Templates that only fit typed sockets
+    /// are added to the rest.
+    Sockets,
+}
+
+impl FromStr for Entrances {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tiles" => Ok(Self::Tiles),
+            "sockets" => Ok(Self::Sockets),
+            _ => Err(format!(
+                "unknown entrances `{}`, expected tiles or sockets",
+                s
+            )),
+        }
+    }
+}
+
+/// Groups sorted entrance indices into untyped sockets of adjacent tiles.
+pub(super) fn spans(entrances: &[i32]) -> Vec<Socket> {
+    let mut sockets: Vec<Socket> = Vec::new();
+    for &i in entrances {
+        match sockets.last_mut() {
+            Some(last) if last.start + last.width == i => last.width += 1,
+            _ => sockets.push(Socket {
+                start: i,
+                width: 1,
+                kind: None,
+            }),
+        }
+    }
+    sockets
+}
+
+impl<R: Rng, O: Observer> MapGenerator<R, O> {
+    /// Templates that rooms are picked from.
+    pub(super) fn templates(&self) -> &'static [Room] {
+        match self.entrances {
+            Entrances::Tiles => &AVAILABLE_ROOMS,
+            Entrances::Sockets => &SOCKET_ROOMS,
+        }
+    }
+
+    /// What rooms can be attached through on the `side` edge of `room`. When
+    /// attaching single tiles, every entrance tile is an untyped socket one
+    /// tile wide.
+    pub(super) fn openings(&self, room: &Room, side: Direction) -> Vec<Socket> {
+        match self.entrances {
+            Entrances::Tiles => room.entrances[side as usize]
+                .iter()
+                .map(|&start| Socket {
+                    start,
+                    width: 1,
+                    kind: None,
+                })
+                .collect(),
+            Entrances::Sockets => room.sockets[side as usize].clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn sockets_from_layout() {
+        let room = Room::new("room", &[&[E, E, D, E], &[E, D, D, D], &[E, E, E, D]])
+            .with_socket_kind(Direction::South, 0, "water");
+        assert_eq!(
+            room.sockets[Direction::North as usize],
+            vec![
+                Socket {
+                    start: 0,
+                    width: 2,
+                    kind: None
+                },
+                Socket {
+                    start: 3,
+                    width: 1,
+                    kind: None
+                },
+            ]
+        );
+        assert_eq!(room.sockets[Direction::West as usize][0].width, 3);
+        assert_eq!(room.sockets[Direction::East as usize][0].width, 1);
+        let water = &room.sockets[Direction::South as usize][0];
+        assert_eq!(water.kind.as_deref(), Some("water"));
+        // As wide, but untyped.
+        assert!(!water.fits(&room.sockets[Direction::West as usize][0]));
+        assert!(!water.fits(&room.sockets[Direction::North as usize][0]));
+        let lava = Socket {
+            kind: Some("lava".to_string()),
+            ..water.clone()
+        };
+        assert!(!water.fits(&lava));
+        assert!(water.fits(&Socket {
+            kind: Some("water".to_string()),
+            ..lava
+        }));
+    }
+
+    #[test]
+    fn only_large_rooms_sit_on_the_ledge() {
+        let ledge = template("ledge").unwrap();
+        let top = &ledge.sockets[Direction::North as usize][0];
+        let gallery = template("gallery").unwrap();
+        assert!(top.fits(&gallery.sockets[Direction::South as usize][0]));
+        // Just as wide and open, but not meant to go there.
+        let hall = Room::new("hall", &[&[E; 8], &[E; 8]]);
+        assert!(!top.fits(&hall.sockets[Direction::South as usize][0]));
+
+        let mut map_gen = MapGenerator::new(128, 128, StdRng::seed_from_u64(3))
+            .with_entrances(Entrances::Sockets);
+        map_gen.by_ref().for_each(drop);
+        let placed = map_gen.placements();
+        let on_ledges: Vec<_> = placed
+            .iter()
+            .filter(|p| {
+                p.parent.is_some_and(|parent| {
+                    let parent = &placed[parent];
+                    parent.room.name == "ledge" && p.rect().max.y == parent.rect().min.y
+                })
+            })
+            .collect();
+        assert!(!on_ledges.is_empty());
+        assert!(on_ledges.iter().all(|p| p.room.name == "gallery"));
+
+        // Maps attaching single tiles have no use for the gallery.
+        let placed: Vec<_> = MapGenerator::new(128, 128, StdRng::seed_from_u64(3)).collect();
+        assert!(placed.iter().all(|p| p.room.name != "gallery"));
+    }
+
+    #[test]
+    fn whole_sockets_are_attached() {
+        let mut map_gen =
+            MapGenerator::new(96, 96, StdRng::seed_from_u64(2)).with_entrances(Entrances::Sockets);
+        map_gen.by_ref().for_each(drop);
+        let placed = map_gen.placements();
+        assert!(placed.len() > 10);
+        for child in &placed[1..] {
+            let parent = &placed[child.parent.unwrap()];
+            // Some socket of the child lines up with a parent socket of the
+            // same width.
+            let attached = CARDINALS.iter().any(|side| {
+                child.room.sockets[*side as usize].iter().any(|socket| {
+                    let outside = child.entrance_tile(*side, socket.start) + side.offset();
+                    parent.room.sockets[side.flip() as usize]
+                        .iter()
+                        .any(|exit| {
+                            parent.entrance_tile(side.flip(), exit.start) == outside
+                                && exit.fits(socket)
+                        })
+                })
+            });
+            assert!(
+                attached,
+                "room {} is not attached through a socket",
+                child.id
+            );
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Bitmaps marking out tiles of the map.
+use crate::rect::Rect;
+use cgmath::Point2;
+use std::fs::File;
+use std::path::Path;
+
+/// A bitmap of `width` by `height` tiles, starting at the origin of the map.
+/// Tiles beyond it are never set.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mask {
+    width: u32,
+    height: u32,
+    /// Goes from top to bottom, west to east.
+    tiles: Vec<bool>,
+}
+
+impl Mask {
+    /// A mask without any tiles set.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            tiles: vec![false; width as usize * height as usize],
+        }
+    }
+
+    /// A mask with the tiles set for which `f` returns true.
+    pub fn from_fn<F>(width: u32, height: u32, f: F) -> Self
+    where
+        F: Fn(Point2<i32>) -> bool,
+    {
+        let mut mask = Self::new(width, height);
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                let pos = Point2::new(x, y);
+                mask.set(pos, f(pos));
+            }
+        }
+        mask
+    }
+
+    /// The ellipse filling a `width` by `height` mask, which is a circle if
+    /// the two are the same.
+    pub fn ellipse(width: u32, height: u32) -> Self {
+        let (rx, ry) = (width as f64 / 2.0, height as f64 / 2.0);
+        Self::from_fn(width, height, |pos| {
+            let dx = (pos.x as f64 + 0.5 - rx) / rx;
+            let dy = (pos.y as f64 + 0.5 - ry) / ry;
+            dx * dx + dy * dy <= 1.0
+        })
+    }
+
+    /// A mask drawn as text, with a line per row and a character per column,
+    /// each `scale` tiles on a side. Spaces and `.` are clear and every other
+    /// character is set, so that glyphs can be drawn with `#`.
+    pub fn from_text(text: &str, scale: u32) -> Self {
+        let lines: Vec<Vec<char>> = text.lines().map(|l| l.chars().collect()).collect();
+        let columns = lines.iter().map(|l| l.len()).max().unwrap_or(0) as u32;
+        let scale = scale.max(1) as i32;
+        Self::from_fn(
+            columns * scale as u32,
+            lines.len() as u32 * scale as u32,
+            |pos| {
+                let line = &lines[(pos.y / scale) as usize];
+                line.get((pos.x / scale) as usize)
+                    .is_some_and(|c| *c != ' ' && *c != '.')
+            },
+        )
+    }
+
+    /// Reads a mask from a PNG image, with a tile per pixel. Bright, opaque
+    /// pixels are set and dark or transparent ones are clear.
+    pub fn from_png(path: &Path) -> Result<Self, String> {
+        let invalid =
+            |e: &dyn std::fmt::Display| format!("cannot read mask `{}`: {}", path.display(), e);
+        let file = File::open(path).map_err(|e| invalid(&e))?;
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(|e| invalid(&e))?;
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut pixels).map_err(|e| invalid(&e))?;
+        let channels = frame.color_type.samples();
+        let alpha = matches!(
+            frame.color_type,
+            png::ColorType::GrayscaleAlpha | png::ColorType::Rgba
+        );
+        let colors = if alpha { channels - 1 } else { channels };
+        Ok(Self::from_fn(frame.width, frame.height, |pos| {
+            let i = (pos.y as usize * frame.width as usize + pos.x as usize) * channels;
+            let pixel = &pixels[i..i + channels];
+            let brightness = pixel[..colors].iter().map(|c| *c as usize).sum::<usize>() / colors;
+            brightness >= 128 && (!alpha || pixel[channels - 1] >= 128)
+        }))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Sets or clears the tile at `pos`, which must lie within the mask.
+    pub fn set(&mut self, pos: Point2<i32>, value: bool) {
+        let i = self.index(pos).expect("tile lies outside of the mask");
+        self.tiles[i] = value;
+    }
+
+    /// Determines if the tile at `pos` is set.
+    pub fn get(&self, pos: Point2<i32>) -> bool {
+        self.index(pos).is_some_and(|i| self.tiles[i])
+    }
+
+    /// Determines if every tile within `r` is set.
+    pub fn covers(&self, r: &Rect) -> bool {
+        (r.min.y..r.max.y).all(|y| (r.min.x..r.max.x).all(|x| self.get(Point2::new(x, y))))
+    }
+
+    /// Determines if any tile within `r` is set.
+    pub fn overlaps(&self, r: &Rect) -> bool {
+        let (max_x, max_y) = (
+            r.max.x.min(self.width as i32),
+            r.max.y.min(self.height as i32),
+        );
+        (r.min.y.max(0)..max_y)
+            .any(|y| (r.min.x.max(0)..max_x).any(|x| self.get(Point2::new(x, y))))
+    }
+
+    fn index(&self, pos: Point2<i32>) -> Option<usize> {
+        let inside =
+            pos.x >= 0 && pos.y >= 0 && pos.x < self.width as i32 && pos.y < self.height as i32;
+        inside.then(|| pos.y as usize * self.width as usize + pos.x as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_overlap() {
+        // A single tile set in the middle.
+        let mask = Mask::from_fn(5, 5, |pos| pos == Point2::new(2, 2));
+        assert!(mask.get(Point2::new(2, 2)));
+        assert!(!mask.get(Point2::new(7, 2)));
+        assert!(mask.overlaps(&Rect::new(Point2::new(-3, -3), Point2::new(3, 3))));
+        assert!(!mask.overlaps(&Rect::new(Point2::new(3, 0), Point2::new(9, 9))));
+    }
+
+    #[test]
+    fn shapes() {
+        let circle = Mask::ellipse(10, 10);
+        assert!(circle.covers(&Rect::new(Point2::new(3, 3), Point2::new(7, 7))));
+        assert!(!circle.get(Point2::new(0, 0)));
+        assert!(!circle.covers(&Rect::new(Point2::new(3, 3), Point2::new(7, 11))));
+
+        let glyph = Mask::from_text("#.#\n###\n# #", 2);
+        assert_eq!((glyph.width(), glyph.height()), (6, 6));
+        assert!(glyph.covers(&Rect::new(Point2::new(0, 2), Point2::new(6, 4))));
+        assert!(!glyph.overlaps(&Rect::new(Point2::new(2, 0), Point2::new(4, 2))));
+        assert!(!glyph.get(Point2::new(3, 5)));
+    }
+
+    #[test]
+    fn png_mask() {
+        let path = std::env::temp_dir().join(format!("mask-{}.png", std::process::id()));
+        let mut encoder = png::Encoder::new(File::create(&path).unwrap(), 3, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        // White, black and transparent white.
+        let pixels = [255, 255, 255, 255, 0, 0, 0, 255, 255, 255, 255, 0];
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+        let mask = Mask::from_png(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((mask.width(), mask.height()), (3, 1));
+        assert!(mask.get(Point2::new(0, 0)));
+        assert!(!mask.get(Point2::new(1, 0)));
+        assert!(!mask.get(Point2::new(2, 0)));
+    }
+}
@@ -0,0 +1,196 @@
+//! Watching a map generator's search as it runs.
+use crate::map_gen::{Direction, RoomPlacement};
+use crate::rect::Rect;
+use crate::stats::MapStats;
+use cgmath::Point2;
+use std::io::Write;
+
+/// Why a candidate room was not placed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RejectReason {
+    /// It overlaps a room that has already been placed.
+    Overlap,
+    /// It has less rock between it and a room it is not connected to than the
+    /// gap and padding ask for.
+    TooClose,
+    /// It lies entirely outside of the map.
+    OutsideMap,
+    /// It does not lie entirely within the shape the map is grown in.
+    OutsideShape,
+    /// Empty tiles along the edge it shares with its parent face dirt.
+    EdgeMismatch,
+    /// A player could not get into it from the first room, or it would cut them
+    /// off from a room placed before.
+    Unreachable,
+    /// It would replace a dead end but does not open into another branch.
+    NoLoop,
+}
+
+/// Receives the steps a `MapGenerator` takes while searching for rooms to
+/// place, so that they can be visualized, logged or measured. Every method does
+/// nothing by default.
+///
+/// Rooms are placed as soon as they fit, but are only returned from the
+/// generator later on, once it gets around to trying their exits.
+pub trait Observer {
+    /// Rooms are about to be tried at the exit of `room` on `side` whose
+    /// entrance tile is `exit`.
+    fn on_exit_tried(&mut self, _room: &RoomPlacement, _side: Direction, _exit: Point2<i32>) {}
+
+    /// A room covering `rect` could not be placed.
+    fn on_candidate_rejected(&mut self, _rect: &Rect, _reason: RejectReason) {}
+
+    /// `placement` has been attached to `parent` through the exit whose entrance
+    /// tile is `exit`.
+    fn on_placed(
+        &mut self,
+        _placement: &RoomPlacement,
+        _parent: &RoomPlacement,
+        _exit: Point2<i32>,
+    ) {
+    }
+
+    /// Every exit of `placement` has been tried.
+    fn on_room_exhausted(&mut self, _placement: &RoomPlacement) {}
+
+    /// The map is finished. `stats` describe the whole map, including the time
+    /// since the generator was created.
+    fn on_finished(&mut self, _stats: &MapStats) {}
+}
+
+/// Ignores everything.
+impl Observer for () {}
+
+/// Reports everything to both observers, first to one then the other.
+impl<A: Observer, B: Observer> Observer for (A, B) {
+    fn on_exit_tried(&mut self, room: &RoomPlacement, side: Direction, exit: Point2<i32>) {
+        self.0.on_exit_tried(room, side, exit);
+        self.1.on_exit_tried(room, side, exit);
+    }
+
+    fn on_candidate_rejected(&mut self, rect: &Rect, reason: RejectReason) {
+        self.0.on_candidate_rejected(rect, reason);
+        self.1.on_candidate_rejected(rect, reason);
+    }
+
+    fn on_placed(&mut self, placement: &RoomPlacement, parent: &RoomPlacement, exit: Point2<i32>) {
+        self.0.on_placed(placement, parent, exit);
+        self.1.on_placed(placement, parent, exit);
+    }
+
+    fn on_room_exhausted(&mut self, placement: &RoomPlacement) {
+        self.0.on_room_exhausted(placement);
+        self.1.on_room_exhausted(placement);
+    }
+
+    fn on_finished(&mut self, stats: &MapStats) {
+        self.0.on_finished(stats);
+        self.1.on_finished(stats);
+    }
+}
+
+/// Writes a line describing every step of the search. Failing to write is
+/// ignored, so that logging never stops generation.
+#[derive(Debug)]
+pub struct Log<W: Write>(pub W);
+
+impl<W: Write> Observer for Log<W> {
+    fn on_exit_tried(&mut self, room: &RoomPlacement, side: Direction, exit: Point2<i32>) {
+        let _ = writeln!(
+            self.0,
+            "exit {} {:?} ({}, {})",
+            room.id, side, exit.x, exit.y
+        );
+    }
+
+    fn on_candidate_rejected(&mut self, rect: &Rect, reason: RejectReason) {
+        let _ = writeln!(
+            self.0,
+            "rejected ({}, {})..({}, {}) {:?}",
+            rect.min.x, rect.min.y, rect.max.x, rect.max.y, reason
+        );
+    }
+
+    fn on_placed(&mut self, placement: &RoomPlacement, parent: &RoomPlacement, exit: Point2<i32>) {
+        let _ = writeln!(
+            self.0,
+            "placed {} {} at ({}, {}) from {} through ({}, {})",
+            placement.id,
+            placement.room.name,
+            placement.pos.x,
+            placement.pos.y,
+            parent.id,
+            exit.x,
+            exit.y
+        );
+    }
+
+    fn on_room_exhausted(&mut self, placement: &RoomPlacement) {
+        let _ = writeln!(self.0, "exhausted {}", placement.id);
+    }
+
+    fn on_finished(&mut self, stats: &MapStats) {
+        let _ = writeln!(self.0, "finished {} rooms", stats.rooms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_gen::MapGenerator;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[derive(Default)]
+    struct Counter {
+        exits: usize,
+        rejected: usize,
+        placed: usize,
+        exhausted: usize,
+        finished: Option<usize>,
+    }
+
+    impl Observer for Counter {
+        fn on_exit_tried(&mut self, _room: &RoomPlacement, _side: Direction, _exit: Point2<i32>) {
+            self.exits += 1;
+        }
+
+        fn on_candidate_rejected(&mut self, _rect: &Rect, _reason: RejectReason) {
+            self.rejected += 1;
+        }
+
+        fn on_placed(&mut self, placement: &RoomPlacement, parent: &RoomPlacement, _: Point2<i32>) {
+            assert_eq!(placement.parent, Some(parent.id));
+            self.placed += 1;
+        }
+
+        fn on_room_exhausted(&mut self, _placement: &RoomPlacement) {
+            self.exhausted += 1;
+        }
+
+        fn on_finished(&mut self, stats: &MapStats) {
+            assert!(self.finished.is_none());
+            self.finished = Some(stats.rooms);
+        }
+    }
+
+    #[test]
+    fn sees_every_placement() {
+        let mut map_gen = MapGenerator::new(64, 64, StdRng::seed_from_u64(0))
+            .with_observer((Counter::default(), Log(Vec::new())));
+        let rooms = map_gen.by_ref().count();
+        assert!(map_gen.next().is_none());
+        let (counter, log) = map_gen.observer();
+        // The first room is placed before the search starts.
+        assert_eq!(counter.placed, rooms - 1);
+        assert_eq!(counter.exhausted, rooms);
+        assert_eq!(counter.finished, Some(rooms));
+        assert!(counter.exits >= counter.placed);
+        assert!(counter.rejected > 0);
+        let log = String::from_utf8(log.0.clone()).unwrap();
+        assert_eq!(
+            log.lines().last(),
+            Some(format!("finished {} rooms", rooms).as_str())
+        );
+    }
+}
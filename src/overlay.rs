@@ -0,0 +1,95 @@
+//! Overlay showing the depth-first search behind the map as it runs.
+use crate::map_gen::{Direction, RoomPlacement};
+use crate::observer::{Observer, RejectReason};
+use crate::rect::Rect;
+use crate::render::Renderer;
+use crate::scene::Scene;
+use cgmath::Point2;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+const LINK_COLOR: (u8, u8, u8) = (128, 128, 128);
+const FRONTIER_COLOR: (u8, u8, u8) = (255, 200, 0);
+const REJECTED_COLOR: (u8, u8, u8) = (255, 0, 0);
+const EXPANDING_COLOR: (u8, u8, u8) = (0, 200, 0);
+
+/// Records what the generator is doing so it can be drawn over the scene.
+#[derive(Debug, Default)]
+pub struct SearchOverlay {
+    /// Id and bounds of the room whose exits are being tried.
+    expanding: Option<(usize, Rect)>,
+    /// Entrance tiles of the exits tried on the expanding room.
+    exits: Vec<Point2<i32>>,
+    /// Rejected candidates along with the number of frames they have been shown.
+    rejected: Vec<(Rect, u32)>,
+}
+
+impl SearchOverlay {
+    /// Number of frames a rejected candidate is shown for.
+    const FLASH_FRAMES: u32 = 8;
+    /// Most rejected candidates shown at once, keeping the most recent ones.
+    const MAX_REJECTED: usize = 256;
+
+    /// Draws the links between parent and child rooms, the outline of every
+    /// room in `frontier`, the recently rejected candidates and the room being
+    /// expanded along with its exits.
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        renderer: &Renderer,
+        scene: &Scene,
+        frontier: &[RoomPlacement],
+    ) {
+        for placement in scene.placements() {
+            let links = placement.parent.iter().chain(placement.joins.iter());
+            for other in links.filter_map(|id| scene.placement(*id)) {
+                let (from, to) = (center(&placement.rect()), center(&other.rect()));
+                renderer.draw_line(canvas, from, to, LINK_COLOR);
+            }
+        }
+        for placement in frontier {
+            renderer.draw_outline(canvas, &placement.rect(), FRONTIER_COLOR);
+        }
+        for (rect, _) in &self.rejected {
+            renderer.draw_outline(canvas, rect, REJECTED_COLOR);
+        }
+        if let Some((_, rect)) = &self.expanding {
+            renderer.draw_outline(canvas, rect, EXPANDING_COLOR);
+            for exit in &self.exits {
+                renderer.draw_tile(canvas, *exit, EXPANDING_COLOR);
+            }
+        }
+    }
+
+    /// Ages the rejected candidates by a frame, dropping those that have been
+    /// shown long enough.
+    pub fn tick(&mut self) {
+        let excess = self.rejected.len().saturating_sub(Self::MAX_REJECTED);
+        self.rejected.drain(..excess);
+        self.rejected.retain_mut(|(_, age)| {
+            *age += 1;
+            *age <= Self::FLASH_FRAMES
+        });
+    }
+}
+
+impl Observer for SearchOverlay {
+    fn on_exit_tried(&mut self, room: &RoomPlacement, _side: Direction, exit: Point2<i32>) {
+        if self.expanding.map(|(id, _)| id) != Some(room.id) {
+            self.expanding = Some((room.id, room.rect()));
+            self.exits.clear();
+        }
+        self.exits.push(exit);
+    }
+
+    fn on_candidate_rejected(&mut self, rect: &Rect, _reason: RejectReason) {
+        self.rejected.push((*rect, 0));
+    }
+}
+
+fn center(rect: &Rect) -> Point2<f64> {
+    Point2::new(
+        (rect.min.x + rect.max.x) as f64 / 2.0,
+        (rect.min.y + rect.max.y) as f64 / 2.0,
+    )
+}
@@ -0,0 +1,237 @@
+//! Which parts of a map a player under gravity can get to.
+//!
+//! The player takes up a single tile and can only be in empty tiles. They stand
+//! on any empty tile above one that is not empty, including tiles that no room
+//! covers, and fall through empty tiles otherwise. From where they stand they
+//! can jump straight up and then move sideways, or walk off a ledge, and fall
+//! wherever they are let go.
+use crate::map_gen::{RoomPlacement, Tile};
+use crate::text_map::TileMap;
+use cgmath::{Point2, Vector2};
+use std::collections::{HashSet, VecDeque};
+
+/// How far the player can move, in tiles.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Movement {
+    /// Tiles the player can rise above where they stand.
+    pub jump_height: u32,
+    /// Tiles the player can move sideways in a single jump or step.
+    pub jump_distance: u32,
+    /// Tiles the player can land below where they left the ground without
+    /// dying.
+    pub fall_tolerance: u32,
+}
+
+impl Default for Movement {
+    fn default() -> Self {
+        Self {
+            jump_height: 3,
+            jump_distance: 4,
+            fall_tolerance: 8,
+        }
+    }
+}
+
+/// Where the player can get to.
+#[derive(Clone, Debug, Default)]
+pub struct Reach {
+    /// Tiles the player can be in, whether standing, jumping or falling.
+    pub visited: HashSet<Point2<i32>>,
+    /// Tiles the player can stand in.
+    pub standing: HashSet<Point2<i32>>,
+}
+
+/// Tiles that the player can get to having started at any of `from`, whether
+/// standing, jumping or falling. `tile_at` gives the tile at a map position, or
+/// None where no room covers it. A start position the player would fall from
+/// counts as leaving the ground there.
+pub fn reachable<F>(tile_at: F, from: &[Point2<i32>], movement: &Movement) -> HashSet<Point2<i32>>
+where
+    F: Fn(Point2<i32>) -> Option<Tile>,
+{
+    explore(tile_at, from, movement, &Reach::default()).visited
+}
+
+/// Like `reachable`, but only returns where the player can get to that `known`
+/// does not already hold. Tiles the player can stand in according to `known`
+/// are not explored again unless they are in `from`.
+pub fn explore<F>(tile_at: F, from: &[Point2<i32>], movement: &Movement, known: &Reach) -> Reach
+where
+    F: Fn(Point2<i32>) -> Option<Tile>,
+{
+    let open = |pos: Point2<i32>| tile_at(pos).is_some_and(|t| t.is_empty());
+    // Lets the player go at `pos`, recording the tiles they fall through.
+    let let_go = |pos: Point2<i32>, takeoff: i32, visited: &mut HashSet<_>| {
+        let landed = landing(&tile_at, pos, takeoff, movement)?;
+        visited.extend((pos.y..=landed.y).map(|y| Point2::new(pos.x, y)));
+        Some(landed)
+    };
+
+    let mut visited = HashSet::new();
+    let mut standing = HashSet::new();
+    let mut queue = VecDeque::new();
+    for &pos in from.iter().filter(|p| open(**p)) {
+        if let Some(landed) = let_go(pos, pos.y, &mut visited) {
+            if known.standing.contains(&landed) || standing.insert(landed) {
+                queue.push_back(landed);
+            }
+        }
+    }
+    let mut landings = Vec::new();
+    loop {
+        for landed in landings.drain(..) {
+            if !known.standing.contains(&landed) && standing.insert(landed) {
+                queue.push_back(landed);
+            }
+        }
+        let pos = match queue.pop_front() {
+            Some(pos) => pos,
+            None => break,
+        };
+        for height in 0..=movement.jump_height as i32 {
+            let apex = pos - Vector2::new(0, height);
+            if !open(apex) {
+                break;
+            }
+            // Jumping straight up always lands back where the jump started.
+            visited.insert(apex);
+            for dir in [-1, 1] {
+                for distance in 1..=movement.jump_distance as i32 {
+                    let at = apex + Vector2::new(dir * distance, 0);
+                    if !open(at) {
+                        break;
+                    }
+                    landings.extend(let_go(at, pos.y, &mut visited));
+                }
+            }
+        }
+    }
+    visited.retain(|pos| !known.visited.contains(pos));
+    Reach { visited, standing }
+}
+
+/// Where the player lands if let go at `pos`, or None if they fall further than
+/// they can survive below `takeoff`.
+pub fn landing<F>(
+    tile_at: F,
+    pos: Point2<i32>,
+    takeoff: i32,
+    movement: &Movement,
+) -> Option<Point2<i32>>
+where
+    F: Fn(Point2<i32>) -> Option<Tile>,
+{
+    let mut landed = pos;
+    while tile_at(landed + Vector2::new(0, 1)).is_some_and(|t| t.is_empty()) {
+        landed.y += 1;
+        if landed.y - takeoff > movement.fall_tolerance as i32 {
+            return None;
+        }
+    }
+    Some(landed)
+}
+
+/// Empty tiles of `placement` that the player could stand on, where `tile_at`
+/// gives the tiles of the map around it.
+pub fn standing_tiles<F>(placement: &RoomPlacement, tile_at: F) -> Vec<Point2<i32>>
+where
+    F: Fn(Point2<i32>) -> Option<Tile>,
+{
+    let r = placement.rect();
+    (r.min().y..r.max().y)
+        .flat_map(|y| (r.min().x..r.max().x).map(move |x| Point2::new(x, y)))
+        .filter(|&pos| {
+            placement.tile_at(pos).is_empty()
+                && !tile_at(pos + Vector2::new(0, 1)).is_some_and(|t| t.is_empty())
+        })
+        .collect()
+}
+
+/// Ids of the rooms that the player cannot get into after starting anywhere in
+/// the first room.
+pub fn unreachable_rooms(placements: &[RoomPlacement], movement: &Movement) -> Vec<usize> {
+    let map = TileMap::from_placements(placements);
+    let start = match placements.iter().find(|p| p.parent.is_none()) {
+        Some(start) => start,
+        None => return Vec::new(),
+    };
+    let reached = reachable(
+        |pos| map.get(pos),
+        &standing_tiles(start, |pos| map.get(pos)),
+        movement,
+    );
+    placements
+        .iter()
+        .filter(|p| {
+            let r = p.rect();
+            !(r.min().y..r.max().y)
+                .any(|y| (r.min().x..r.max().x).any(|x| reached.contains(&Point2::new(x, y))))
+        })
+        .map(|p| p.id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_gen::{Room, Tile::*};
+
+    const MOVEMENT: Movement = Movement {
+        jump_height: 2,
+        jump_distance: 2,
+        fall_tolerance: 3,
+    };
+
+    #[test]
+    fn jumps_and_falls() {
+        // A floor at the bottom, a ledge two tiles up and a shelf three above
+        // that.
+        let room = Room::new(
+            "room",
+            &[
+                &[Empty, Empty, Empty, Empty, Empty],
+                &[Dirt, Dirt, Empty, Empty, Empty],
+                &[Empty, Empty, Empty, Empty, Empty],
+                &[Empty, Empty, Empty, Empty, Empty],
+                &[Empty, Empty, Empty, Dirt, Dirt],
+                &[Empty, Empty, Empty, Empty, Empty],
+                &[Dirt, Dirt, Dirt, Dirt, Dirt],
+            ],
+        )
+        .place(Point2::new(0, 0), 0, None);
+        let tile_at = |pos: Point2<i32>| room.rect().contains(pos).then(|| room.tile_at(pos));
+        let reached = reachable(tile_at, &[Point2::new(0, 5)], &MOVEMENT);
+        assert!(reached.contains(&Point2::new(3, 3)));
+        assert!(!reached.contains(&Point2::new(0, 0)));
+        // Dropping from the shelf onto the ledge is a fall of three tiles.
+        let from_shelf = reachable(tile_at, &[Point2::new(0, 0)], &MOVEMENT);
+        assert!(from_shelf.contains(&Point2::new(3, 3)));
+        let fragile = Movement {
+            fall_tolerance: 2,
+            ..MOVEMENT
+        };
+        let from_shelf = reachable(tile_at, &[Point2::new(0, 0)], &fragile);
+        assert!(from_shelf.contains(&Point2::new(1, 0)));
+        assert!(!from_shelf.contains(&Point2::new(3, 3)));
+    }
+
+    #[test]
+    fn unreachable_shaft() {
+        let floor = Room::new("floor", &[&[Empty, Empty, Empty], &[Dirt, Dirt, Dirt]]);
+        let shaft = Room::new("shaft", &[&[Dirt, Empty, Dirt][..]; 5]);
+        let top = Room::new("top", &[&[Empty, Empty, Empty], &[Dirt, Empty, Dirt]]);
+        let placements = vec![
+            floor.place(Point2::new(0, 0), 0, None),
+            floor.place(Point2::new(3, 0), 1, Some(0)),
+            shaft.place(Point2::new(3, -5), 2, Some(1)),
+            top.place(Point2::new(3, -7), 3, Some(2)),
+        ];
+        // The shaft is too high to climb.
+        assert_eq!(unreachable_rooms(&placements, &MOVEMENT), vec![3]);
+        let high_jump = Movement {
+            jump_height: 7,
+            ..MOVEMENT
+        };
+        assert!(unreachable_rooms(&placements, &high_jump).is_empty());
+    }
+}
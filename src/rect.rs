@@ -10,6 +10,34 @@ pub struct Rect {
 }
 
 impl Rect {
+    /// The rectangle from `min` up to but not including `max`.
+    pub fn new(min: Point2<i32>, max: Point2<i32>) -> Self {
+        Self { min, max }
+    }
+
+    /// Top left corner.
+    pub fn min(&self) -> Point2<i32> {
+        self.min
+    }
+
+    /// Bottom right corner, exclusive.
+    pub fn max(&self) -> Point2<i32> {
+        self.max
+    }
+
+    /// The rectangle grown by `by` tiles on every side.
+    pub fn padded(&self, by: i32) -> Self {
+        Self {
+            min: self.min - Vector2::new(by, by),
+            max: self.max + Vector2::new(by, by),
+        }
+    }
+
+    /// Determines if the tile at `pos` lies within the rectangle.
+    pub fn contains(&self, pos: Point2<i32>) -> bool {
+        pos.x >= self.min.x && pos.x < self.max.x && pos.y >= self.min.y && pos.y < self.max.y
+    }
+
     /// Determines if the two rectangles are overlapping.
     pub fn overlaps(&self, rhs: &Self) -> bool {
         self.min.x < rhs.max.x
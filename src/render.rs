@@ -0,0 +1,168 @@
+//! Drawing placed rooms onto an SDL canvas.
+use crate::camera::Camera;
+use crate::map_gen::{Room, RoomPlacement};
+use crate::rect::Rect;
+use cgmath::Point2;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::surface::Surface;
+use sdl2::video::{Window, WindowContext};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Name of a room, hash of its layout and colour of its empty tiles.
+type TextureKey = (String, u64, (u8, u8, u8));
+
+/// Draws rooms by copying textures that are built once per room template and
+/// colour, instead of uploading a fresh texture for every room drawn. Rooms
+/// whose doors were opened or sealed get textures of their own, as the key
+/// includes a hash of the layout.
+pub struct Renderer<'a> {
+    texture_creator: &'a TextureCreator<WindowContext>,
+    dirt_color: (u8, u8, u8),
+    camera: Camera,
+    textures: HashMap<TextureKey, Texture<'a>>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(
+        texture_creator: &'a TextureCreator<WindowContext>,
+        dirt_color: (u8, u8, u8),
+        camera: Camera,
+    ) -> Self {
+        Self {
+            texture_creator,
+            dirt_color,
+            camera,
+            textures: HashMap::new(),
+        }
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    /// Fills the whole canvas with dirt.
+    pub fn draw_background(&self, canvas: &mut Canvas<Window>) {
+        let c = self.dirt_color;
+        canvas.set_draw_color(Color::RGB(c.0, c.1, c.2));
+        canvas.clear();
+    }
+
+    /// Draws the room with its empty tiles in `empty_color`.
+    pub fn draw(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        placement: &RoomPlacement,
+        empty_color: (u8, u8, u8),
+    ) {
+        let room = &placement.room;
+        let dst = self.to_window(placement.pos, room.width, room.height);
+        if !dst.has_intersection(canvas.viewport()) {
+            return;
+        }
+        let mut hasher = DefaultHasher::new();
+        room.layout.hash(&mut hasher);
+        let key = (room.name.clone(), hasher.finish(), empty_color);
+        let (texture_creator, dirt_color) = (self.texture_creator, self.dirt_color);
+        let texture = self
+            .textures
+            .entry(key)
+            .or_insert_with(|| build_texture(texture_creator, room, empty_color, dirt_color));
+        canvas.copy(texture, None, Some(dst)).unwrap();
+    }
+
+    /// Draws a single tile of the map.
+    pub fn draw_tile(&self, canvas: &mut Canvas<Window>, pos: Point2<i32>, color: (u8, u8, u8)) {
+        canvas.set_draw_color(Color::RGB(color.0, color.1, color.2));
+        canvas.fill_rect(self.to_window(pos, 1, 1)).unwrap();
+    }
+
+    /// Draws the outline of the tiles covered by `rect`.
+    pub fn draw_outline(&self, canvas: &mut Canvas<Window>, rect: &Rect, color: (u8, u8, u8)) {
+        let size = rect.max - rect.min;
+        canvas.set_draw_color(Color::RGB(color.0, color.1, color.2));
+        canvas
+            .draw_rect(self.to_window(rect.min, size.x as u32, size.y as u32))
+            .unwrap();
+    }
+
+    /// Draws a line between two map positions.
+    pub fn draw_line(
+        &self,
+        canvas: &mut Canvas<Window>,
+        from: Point2<f64>,
+        to: Point2<f64>,
+        color: (u8, u8, u8),
+    ) {
+        let (from, to) = (self.camera.to_window(from), self.camera.to_window(to));
+        canvas.set_draw_color(Color::RGB(color.0, color.1, color.2));
+        canvas
+            .draw_line(
+                rect::Point::new(from.x as i32, from.y as i32),
+                rect::Point::new(to.x as i32, to.y as i32),
+            )
+            .unwrap();
+    }
+
+    /// Window rectangle covered by `width` by `height` tiles starting at `pos`.
+    /// Both corners are rounded the same way so that neighbouring rooms never
+    /// leave gaps between them.
+    fn to_window(&self, pos: Point2<i32>, width: u32, height: u32) -> rect::Rect {
+        let min = self
+            .camera
+            .to_window(Point2::new(pos.x as f64, pos.y as f64));
+        let max = self.camera.to_window(Point2::new(
+            (pos.x + width as i32) as f64,
+            (pos.y + height as i32) as f64,
+        ));
+        let (x, y) = (min.x.floor() as i32, min.y.floor() as i32);
+        rect::Rect::new(
+            x,
+            y,
+            (max.x.floor() as i32 - x).max(1) as u32,
+            (max.y.floor() as i32 - y).max(1) as u32,
+        )
+    }
+
+    /// Drops every cached texture.
+    pub fn clear(&mut self) {
+        self.textures.clear();
+    }
+}
+
+fn build_texture<'a>(
+    texture_creator: &'a TextureCreator<WindowContext>,
+    room: &Room,
+    empty_color: (u8, u8, u8),
+    dirt_color: (u8, u8, u8),
+) -> Texture<'a> {
+    let mut pixels: Vec<u8> = room
+        .layout
+        .iter()
+        .flatten()
+        .flat_map(|t| {
+            let c = if t.is_empty() {
+                empty_color
+            } else {
+                dirt_color
+            };
+            vec![c.0, c.1, c.2]
+        })
+        .collect();
+    let surface = Surface::from_data(
+        &mut pixels[..],
+        room.width,
+        room.height,
+        room.width * 3,
+        PixelFormatEnum::RGB24,
+    )
+    .unwrap();
+    surface.as_texture(texture_creator).unwrap()
+}
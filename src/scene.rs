@@ -0,0 +1,91 @@
+//! Retained copy of everything shown in the viewer, so it can be redrawn at will.
+use crate::map_gen::RoomPlacement;
+use crate::rect::Rect;
+#[cfg(feature = "sdl")]
+use crate::render::Renderer;
+use cgmath::Point2;
+#[cfg(feature = "sdl")]
+use sdl2::{render::Canvas, video::Window};
+use std::collections::HashMap;
+
+/// Every room placed so far along with the colour it is drawn in.
+#[derive(Debug, Default)]
+pub struct Scene {
+    placements: Vec<RoomPlacement>,
+    colors: Vec<(u8, u8, u8)>,
+    /// Index into `placements` by room id.
+    index: HashMap<usize, usize>,
+}
+
+impl Scene {
+    /// Adds a room to the scene, replacing any room with the same id.
+    pub fn add(&mut self, placement: RoomPlacement, color: (u8, u8, u8)) {
+        match self.index.get(&placement.id) {
+            Some(&i) => {
+                self.placements[i] = placement;
+                self.colors[i] = color;
+            }
+            None => {
+                self.index.insert(placement.id, self.placements.len());
+                self.placements.push(placement);
+                self.colors.push(color);
+            }
+        }
+    }
+
+    pub fn placements(&self) -> &[RoomPlacement] {
+        &self.placements
+    }
+
+    /// The room with the given id.
+    pub fn placement(&self, id: usize) -> Option<&RoomPlacement> {
+        self.index.get(&id).map(|&i| &self.placements[i])
+    }
+
+    /// Colour of the room at `index` in `placements`.
+    pub fn color(&self, index: usize) -> (u8, u8, u8) {
+        self.colors[index]
+    }
+
+    /// Smallest rectangle containing every room, or None if the scene is empty.
+    pub fn bounds(&self) -> Option<Rect> {
+        self.placements
+            .iter()
+            .map(|p| p.rect())
+            .reduce(|a, b| Rect {
+                min: Point2::new(a.min.x.min(b.min.x), a.min.y.min(b.min.y)),
+                max: Point2::new(a.max.x.max(b.max.x), a.max.y.max(b.max.y)),
+            })
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Draws the whole scene over a clean background.
+    #[cfg(feature = "sdl")]
+    pub fn draw(&self, canvas: &mut Canvas<Window>, renderer: &mut Renderer) {
+        renderer.draw_background(canvas);
+        for (placement, color) in self.placements.iter().zip(&self.colors) {
+            renderer.draw(canvas, placement, *color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_gen::{Room, Tile::*};
+
+    #[test]
+    fn replaces_same_id() {
+        let room = Room::new("room", &[&[Empty]]);
+        let mut scene = Scene::default();
+        scene.add(room.place(Point2::new(0, 0), 0, None), (0, 0, 0));
+        scene.add(room.place(Point2::new(1, 0), 1, Some(0)), (1, 1, 1));
+        scene.add(room.place(Point2::new(2, 0), 1, Some(0)), (2, 2, 2));
+        assert_eq!(scene.placements().len(), 2);
+        assert_eq!(scene.placements()[1].pos, Point2::new(2, 0));
+        assert_eq!(scene.color(1), (2, 2, 2));
+    }
+}
@@ -0,0 +1,211 @@
+//! Statistics describing a generated map.
+use crate::map_gen::{add_border, RoomPlacement, Tile, CARDINALS};
+use crate::text_map::TileMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::Duration;
+
+/// Summary of a finished map.
+#[derive(Clone, Debug, Serialize)]
+pub struct MapStats {
+    /// Total number of rooms, including corridors and connectors.
+    pub rooms: usize,
+    /// Number of rooms created from each template, by template name.
+    pub rooms_per_template: BTreeMap<String, usize>,
+    /// Empty tiles within the bounding box.
+    pub empty_tiles: usize,
+    /// Dirt tiles within the bounding box, including those not covered by a room.
+    pub dirt_tiles: usize,
+    /// Empty tiles divided by dirt tiles, or zero if there is no dirt.
+    pub empty_dirt_ratio: f64,
+    /// Fraction of the bounding box covered by rooms, or zero if it is empty.
+    pub fill_ratio: f64,
+    pub bounding_box: Bounds,
+    /// Rooms other than the first connected to only one other room.
+    pub dead_ends: usize,
+    /// Number of rooms between the first room and the room furthest from it.
+    pub longest_path: usize,
+    /// Number of rooms that had the index's number of rooms attached to them.
+    pub branch_factors: Vec<usize>,
+    /// Entrance tiles that do not open into another room.
+    pub unattached_entrances: usize,
+    /// Time taken to generate the map, in seconds.
+    pub generation_secs: f64,
+}
+
+/// Smallest rectangle containing every room, with an exclusive max.
+#[derive(Copy, Clone, Debug, Default, Serialize)]
+pub struct Bounds {
+    pub min_x: i32,
+    pub min_y: i32,
+    pub max_x: i32,
+    pub max_y: i32,
+}
+
+impl MapStats {
+    /// Computes the statistics of the map made up of `placements`, which took
+    /// `generation_time` to generate.
+    pub fn compute(placements: &[RoomPlacement], generation_time: Duration) -> Self {
+        let index: HashMap<usize, usize> = placements
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.id, i))
+            .collect();
+
+        let mut rooms_per_template = BTreeMap::new();
+        let mut bounds: Option<Bounds> = None;
+        let mut connections = vec![Vec::new(); placements.len()];
+        let mut children = vec![0; placements.len()];
+        let mut borders = HashMap::new();
+        for (i, placement) in placements.iter().enumerate() {
+            *rooms_per_template
+                .entry(placement.room.name.clone())
+                .or_insert(0) += 1;
+            let r = placement.rect();
+            let b = bounds.get_or_insert(Bounds {
+                min_x: r.min.x,
+                min_y: r.min.y,
+                max_x: r.max.x,
+                max_y: r.max.y,
+            });
+            b.min_x = b.min_x.min(r.min.x);
+            b.min_y = b.min_y.min(r.min.y);
+            b.max_x = b.max_x.max(r.max.x);
+            b.max_y = b.max_y.max(r.max.y);
+            for other in placement.parent.iter().chain(placement.joins.iter()) {
+                if let Some(&j) = index.get(other) {
+                    connections[i].push(j);
+                    connections[j].push(i);
+                }
+            }
+            if let Some(&j) = placement.parent.and_then(|p| index.get(&p)) {
+                children[j] += 1;
+            }
+            add_border(&mut borders, placement);
+        }
+        let bounds = bounds.unwrap_or_default();
+        // Rooms placed by hand may overlap, so count tiles on the map rather
+        // than in every room.
+        let map = TileMap::from_placements(placements);
+        let area = map.width as usize * map.height as usize;
+        let covered = map.tiles.iter().flatten().filter(|t| t.is_some()).count();
+        let empty_tiles = map
+            .tiles
+            .iter()
+            .flatten()
+            .filter(|t| **t == Some(Tile::Empty))
+            .count();
+
+        let mut branch_factors = vec![0; children.iter().max().map_or(0, |m| m + 1)];
+        for &c in &children {
+            branch_factors[c] += 1;
+        }
+
+        // Breadth-first search from the first room for the furthest room.
+        let mut longest_path = 0;
+        let start = placements.iter().position(|p| p.parent.is_none());
+        if let Some(start) = start {
+            let mut dist = vec![None; placements.len()];
+            dist[start] = Some(0);
+            let mut queue = VecDeque::from(vec![start]);
+            while let Some(i) = queue.pop_front() {
+                let d = dist[i].unwrap();
+                longest_path = longest_path.max(d);
+                for &j in &connections[i] {
+                    if dist[j].is_none() {
+                        dist[j] = Some(d + 1);
+                        queue.push_back(j);
+                    }
+                }
+            }
+        }
+
+        let mut unattached_entrances = 0;
+        for placement in placements {
+            for side in &CARDINALS {
+                for &entrance in &placement.room.entrances[*side as usize] {
+                    let outside = placement.entrance_tile(*side, entrance) + side.offset();
+                    let attached = borders
+                        .get(&outside)
+                        .and_then(|id| index.get(id))
+                        .is_some_and(|&j| placements[j].tile_at(outside).is_empty());
+                    if !attached {
+                        unattached_entrances += 1;
+                    }
+                }
+            }
+        }
+
+        let dirt_tiles = area - empty_tiles;
+        let ratio = |a: usize, b: usize| if b == 0 { 0.0 } else { a as f64 / b as f64 };
+        Self {
+            rooms: placements.len(),
+            rooms_per_template,
+            empty_tiles,
+            dirt_tiles,
+            empty_dirt_ratio: ratio(empty_tiles, dirt_tiles),
+            fill_ratio: ratio(covered, area),
+            bounding_box: bounds,
+            dead_ends: (0..placements.len())
+                .filter(|i| Some(*i) != start && connections[*i].len() == 1)
+                .count(),
+            longest_path,
+            branch_factors,
+            unattached_entrances,
+            generation_secs: generation_time.as_secs_f64(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_gen::{Room, Tile::*};
+    use cgmath::Point2;
+
+    #[test]
+    fn corridor_stats() {
+        let corridor = Room::new(
+            "corridor",
+            &[
+                &[Dirt, Dirt, Dirt],
+                &[Empty, Empty, Empty],
+                &[Dirt, Dirt, Dirt],
+            ],
+        );
+        let placements = vec![
+            corridor.place(Point2::new(0, 0), 0, None),
+            corridor.place(Point2::new(3, 0), 1, Some(0)),
+            corridor.place(Point2::new(6, 0), 2, Some(1)),
+        ];
+        let stats = MapStats::compute(&placements, Duration::from_secs(1));
+        assert_eq!(stats.rooms, 3);
+        assert_eq!(stats.rooms_per_template["corridor"], 3);
+        assert_eq!(stats.empty_tiles, 9);
+        assert_eq!(stats.dirt_tiles, 18);
+        assert_eq!(stats.fill_ratio, 1.0);
+        // Only the last room, not the first.
+        assert_eq!(stats.dead_ends, 1);
+        assert_eq!(stats.longest_path, 2);
+        assert_eq!(stats.branch_factors, vec![1, 2]);
+        // The west end of the first room and the east end of the last.
+        assert_eq!(stats.unattached_entrances, 2);
+    }
+
+    #[test]
+    fn overlapping_and_empty_maps() {
+        let open = Room::new("open", &[&[Empty, Empty], &[Empty, Empty]]);
+        let placements = vec![
+            open.place(Point2::new(0, 0), 0, None),
+            open.place(Point2::new(1, 1), 1, None),
+        ];
+        let stats = MapStats::compute(&placements, Duration::from_secs(1));
+        assert_eq!(stats.empty_tiles, 7);
+        assert_eq!(stats.dirt_tiles, 2);
+        assert_eq!(stats.fill_ratio, 7.0 / 9.0);
+
+        let stats = MapStats::compute(&[], Duration::from_secs(1));
+        assert_eq!((stats.empty_dirt_ratio, stats.fill_ratio), (0.0, 0.0));
+        assert_eq!(stats.dead_ends, 0);
+    }
+}
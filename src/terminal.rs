@@ -0,0 +1,115 @@
+//! Drawing the map as text, for terminals without a display.
+use crate::scene::Scene;
+use crate::theme::Color;
+use std::fmt::Write;
+
+/// How tiles are turned into characters.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Charset {
+    /// Two tiles per character, stacked, using the upper half block with 24-bit
+    /// ANSI colours for each half.
+    HalfBlock,
+    /// One tile per character, `#` for dirt and `.` for empty, without colours.
+    Ascii,
+}
+
+impl Charset {
+    /// Number of map rows that fit in `rows` rows of characters.
+    pub fn tile_rows(self, rows: u32) -> u32 {
+        match self {
+            Self::HalfBlock => rows * 2,
+            Self::Ascii => rows,
+        }
+    }
+}
+
+/// Renders the part of `scene` in the top left `cols` by `rows` characters as
+/// text, starting from the top left of the terminal and overwriting whatever
+/// was shown before.
+pub fn draw(scene: &Scene, dirt: Color, charset: Charset, cols: u32, rows: u32) -> String {
+    let (width, height) = (cols as usize, charset.tile_rows(rows) as usize);
+    // None is dirt, which is drawn differently depending on the charset.
+    let mut grid: Vec<Option<Color>> = vec![None; width * height];
+    let mut set = |x: i32, y: i32, color: Option<Color>| {
+        if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+            grid[y as usize * width + x as usize] = color;
+        }
+    };
+    for (i, placement) in scene.placements().iter().enumerate() {
+        let color = scene.color(i);
+        for (y, row) in placement.room.layout.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                let color = if tile.is_empty() { Some(color) } else { None };
+                set(
+                    placement.pos.x + x as i32,
+                    placement.pos.y + y as i32,
+                    color,
+                );
+            }
+        }
+    }
+
+    let mut out = String::from("\x1b[H");
+    match charset {
+        Charset::HalfBlock => {
+            let mut last = None;
+            for y in (0..height).step_by(2) {
+                for x in 0..width {
+                    let top = grid[y * width + x].unwrap_or(dirt);
+                    let bottom = grid
+                        .get((y + 1) * width + x)
+                        .copied()
+                        .flatten()
+                        .unwrap_or(dirt);
+                    if last != Some((top, bottom)) {
+                        write!(
+                            out,
+                            "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                            top.0, top.1, top.2, bottom.0, bottom.1, bottom.2
+                        )
+                        .unwrap();
+                        last = Some((top, bottom));
+                    }
+                    out.push('▀');
+                }
+                out.push_str("\x1b[0m\r\n");
+                last = None;
+            }
+        }
+        Charset::Ascii => {
+            for row in grid.chunks(width.max(1)) {
+                out.extend(row.iter().map(|c| if c.is_some() { '.' } else { '#' }));
+                out.push_str("\r\n");
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_gen::{Room, Tile::*};
+    use cgmath::Point2;
+
+    #[test]
+    fn ascii_crops_to_size() {
+        let room = Room::new("room", &[&[Dirt, Empty, Dirt], &[Empty, Empty, Empty]]);
+        let mut scene = Scene::default();
+        scene.add(room.place(Point2::new(1, 0), 0, None), (1, 2, 3));
+        let text = draw(&scene, (0, 0, 0), Charset::Ascii, 3, 3);
+        assert_eq!(text, "\x1b[H##.\r\n#..\r\n###\r\n");
+    }
+
+    #[test]
+    fn half_blocks() {
+        let room = Room::new("room", &[&[Empty], &[Dirt]]);
+        let mut scene = Scene::default();
+        scene.add(room.place(Point2::new(0, 0), 0, None), (1, 2, 3));
+        let text = draw(&scene, (9, 9, 9), Charset::HalfBlock, 2, 1);
+        assert_eq!(
+            text,
+            "\x1b[H\x1b[38;2;1;2;3;48;2;9;9;9m▀\x1b[38;2;9;9;9;48;2;9;9;9m▀\x1b[0m\r\n"
+        );
+    }
+}
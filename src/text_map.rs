@@ -0,0 +1,298 @@
+//! Plain-text maps, for diffing, bug reports and golden files.
+//!
+//! Tiles use the same letters as the room templates: `E` for empty and `D` for
+//! dirt, with `.` for tiles that no room covers. Lines starting with `#` are
+//! annotations. `# origin X Y` gives the map position of the top left tile, and
+//! each `# room ID NAME X Y WIDTH HEIGHT PARENT JOINS` line describes a room,
+//! with `-` for a missing parent or joined room. Names cannot contain
+//! whitespace.
+use crate::map_gen::{self, Room, RoomPlacement, Tile};
+use cgmath::Point2;
+use std::convert::TryInto;
+use std::fmt;
+
+/// A dense grid of tiles covering a rectangle of the map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TileMap {
+    /// Map position of the top left tile.
+    pub origin: Point2<i32>,
+    pub width: u32,
+    pub height: u32,
+    /// Rows from top to bottom. None where no room covers the tile.
+    pub tiles: Vec<Vec<Option<Tile>>>,
+}
+
+impl TileMap {
+    /// Rasterizes the smallest rectangle containing every room.
+    pub fn from_placements(placements: &[RoomPlacement]) -> Self {
+        let rects: Vec<_> = placements.iter().map(|p| p.rect()).collect();
+        let min_x = rects.iter().map(|r| r.min().x).min().unwrap_or(0);
+        let min_y = rects.iter().map(|r| r.min().y).min().unwrap_or(0);
+        let max_x = rects.iter().map(|r| r.max().x).max().unwrap_or(0);
+        let max_y = rects.iter().map(|r| r.max().y).max().unwrap_or(0);
+        let (width, height) = ((max_x - min_x) as u32, (max_y - min_y) as u32);
+        let mut tiles = vec![vec![None; width as usize]; height as usize];
+        for placement in placements {
+            for (y, row) in placement.room.layout.iter().enumerate() {
+                for (x, tile) in row.iter().enumerate() {
+                    let (mx, my) = (placement.pos.x - min_x, placement.pos.y - min_y);
+                    tiles[my as usize + y][mx as usize + x] = Some(*tile);
+                }
+            }
+        }
+        Self {
+            origin: Point2::new(min_x, min_y),
+            width,
+            height,
+            tiles,
+        }
+    }
+
+    /// The tile at a map position, or None if no room covers it or it lies
+    /// outside of the map.
+    pub fn get(&self, pos: Point2<i32>) -> Option<Tile> {
+        let x = pos.x.checked_sub(self.origin.x)?;
+        let y = pos.y.checked_sub(self.origin.y)?;
+        if x < 0 || y < 0 {
+            return None;
+        }
+        *self.tiles.get(y as usize)?.get(x as usize)?
+    }
+}
+
+/// Writes the grid without any annotations.
+impl fmt::Display for TileMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in &self.tiles {
+            for tile in row {
+                let c = match tile {
+                    Some(Tile::Empty) => 'E',
+                    Some(Tile::Dirt) => 'D',
+                    None => '.',
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes the map made up of `placements` as text. If `annotate` is set, the
+/// origin and every room are written as well, so that `import` can recover the
+/// placements. Fails if a room to annotate has a name that is empty or contains
+/// whitespace.
+pub fn export(placements: &[RoomPlacement], annotate: bool) -> Result<String, String> {
+    let map = TileMap::from_placements(placements);
+    let mut out = String::new();
+    if annotate {
+        out += &format!("# origin {} {}\n", map.origin.x, map.origin.y);
+        let id = |id: Option<usize>| id.map_or("-".to_string(), |id| id.to_string());
+        for p in placements {
+            if p.room.name.is_empty() || p.room.name.contains(char::is_whitespace) {
+                return Err(format!(
+                    "room {}: `{}` cannot be written as a single word",
+                    p.id, p.room.name
+                ));
+            }
+            out += &format!(
+                "# room {} {} {} {} {} {} {} {}\n",
+                p.id,
+                p.room.name,
+                p.pos.x,
+                p.pos.y,
+                p.room.width,
+                p.room.height,
+                id(p.parent),
+                id(p.joins)
+            );
+        }
+    }
+    Ok(out + &map.to_string())
+}
+
+/// Reads a map written by `export`, along with its rooms if it is annotated.
+/// Room layouts are taken from the grid. Rooms named after a template get the
+/// types of its sockets.
+pub fn import(text: &str) -> Result<(TileMap, Vec<RoomPlacement>), String> {
+    let mut origin = Point2::new(0, 0);
+    let mut rooms = Vec::new();
+    let mut tiles = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let err = |msg: &str| format!("line {}: {}", n + 1, msg);
+        if let Some(annotation) = line.strip_prefix('#') {
+            let words: Vec<&str> = annotation.split_whitespace().collect();
+            match words.first() {
+                Some(&"origin") => {
+                    let [x, y] = parse_numbers(&words[1..]).map_err(|e| err(&e))?;
+                    origin = Point2::new(x, y);
+                }
+                Some(&"room") if words.len() == 9 => {
+                    let [id, x, y, width, height] =
+                        parse_numbers(&[words[1], words[3], words[4], words[5], words[6]])
+                            .map_err(|e| err(&e))?;
+                    let [parent, joins] = parse_ids(&words[7..]).map_err(|e| err(&e))?;
+                    rooms.push((
+                        words[2].to_string(),
+                        [id, x, y, width, height],
+                        parent,
+                        joins,
+                    ));
+                }
+                Some(&"room") => return Err(err("a room needs eight fields")),
+                // Anything else is a comment.
+                _ => (),
+            }
+            continue;
+        }
+        let row = line
+            .chars()
+            .map(|c| match c {
+                'E' => Ok(Some(Tile::Empty)),
+                'D' => Ok(Some(Tile::Dirt)),
+                '.' => Ok(None),
+                _ => Err(err(&format!("unknown tile `{}`", c))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if tiles
+            .first()
+            .is_some_and(|first: &Vec<_>| first.len() != row.len())
+        {
+            return Err(err("rows have different lengths"));
+        }
+        tiles.push(row);
+    }
+    let map = TileMap {
+        origin,
+        width: tiles.first().map_or(0, |row| row.len() as u32),
+        height: tiles.len() as u32,
+        tiles,
+    };
+
+    let mut placements = Vec::new();
+    for (name, [id, x, y, width, height], parent, joins) in rooms {
+        let (max_x, max_y) = match (x.checked_add(width), y.checked_add(height)) {
+            (Some(max_x), Some(max_y)) if id >= 0 && width > 0 && height > 0 => (max_x, max_y),
+            _ => return Err(format!("room {}: invalid id or size", id)),
+        };
+        let layout = (y..max_y)
+            .map(|ty| {
+                (x..max_x)
+                    .map(|tx| map.get(Point2::new(tx, ty)))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| format!("room {} is not entirely covered by tiles", id))?;
+        let mut room = Room::from_layout(name, layout);
+        if let Some(template) = map_gen::template(&room.name) {
+            room.copy_socket_kinds(template);
+        }
+        let mut placement = room.place(Point2::new(x, y), id as usize, parent);
+        placement.joins = joins;
+        placements.push(placement);
+    }
+    Ok((map, placements))
+}
+
+fn parse_numbers<const N: usize>(words: &[&str]) -> Result<[i32; N], String> {
+    let numbers = words
+        .iter()
+        .map(|w| w.parse().map_err(|_| format!("`{}` is not a number", w)))
+        .collect::<Result<Vec<i32>, _>>()?;
+    numbers
+        .try_into()
+        .map_err(|_| format!("expected {} numbers", N))
+}
+
+fn parse_ids<const N: usize>(words: &[&str]) -> Result<[Option<usize>; N], String> {
+    let ids = words
+        .iter()
+        .map(|w| match *w {
+            "-" => Ok(None),
+            _ => w
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("`{}` is not a room id", w)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    ids.try_into()
+        .map_err(|_| format!("expected {} room ids", N))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_gen::{MapGenerator, Tile::*};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const GOLDEN: &str = "\
+# origin 4 2
+# room 0 hall 4 2 3 2 - -
+# room 1 stub 7 3 1 1 0 -
+EEE.
+DDDE
+";
+
+    #[test]
+    fn golden() {
+        let hall = Room::new("hall", &[&[Empty, Empty, Empty], &[Dirt, Dirt, Dirt]]);
+        let stub = Room::new("stub", &[&[Empty]]);
+        let placements = vec![
+            hall.place(Point2::new(4, 2), 0, None),
+            stub.place(Point2::new(7, 3), 1, Some(0)),
+        ];
+        assert_eq!(export(&placements, true).unwrap(), GOLDEN);
+        assert_eq!(export(&placements, false).unwrap(), "EEE.\nDDDE\n");
+
+        let (map, imported) = import(GOLDEN).unwrap();
+        assert_eq!(map.get(Point2::new(7, 2)), None);
+        assert_eq!(map.get(Point2::new(7, 3)), Some(Empty));
+        assert_eq!(imported[1].parent, Some(0));
+        assert_eq!(imported[0].room.entrances, hall.entrances);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut map_gen = MapGenerator::new(64, 64, StdRng::seed_from_u64(7)).with_loops(2);
+        map_gen.by_ref().for_each(drop);
+        let text = export(map_gen.placements(), true).unwrap();
+        let (map, placements) = import(&text).unwrap();
+        assert_eq!(map, TileMap::from_placements(map_gen.placements()));
+        assert_eq!(export(&placements, true).unwrap(), text);
+    }
+
+    #[test]
+    fn socket_kinds_survive() {
+        let mut map_gen = MapGenerator::new(64, 64, StdRng::seed_from_u64(7))
+            .with_entrances(crate::map_gen::Entrances::Sockets);
+        map_gen.by_ref().for_each(drop);
+        let (_, placements) = import(&export(map_gen.placements(), true).unwrap()).unwrap();
+        assert!(placements.iter().any(|p| p.room.name == "ledge"));
+        for (imported, placed) in placements.iter().zip(map_gen.placements()) {
+            assert_eq!(imported.room.sockets, placed.room.sockets);
+        }
+    }
+
+    #[test]
+    fn names_are_single_words() {
+        let room = Room::new("two words", &[&[Empty]]);
+        let placements = vec![room.place(Point2::new(0, 0), 0, None)];
+        assert!(export(&placements, true)
+            .unwrap_err()
+            .contains("`two words`"));
+        assert_eq!(export(&placements, false).unwrap(), "E\n");
+    }
+
+    #[test]
+    fn bad_input() {
+        assert!(import("EX\n").unwrap_err().contains("unknown tile `X`"));
+        assert!(import("EE\nE\n").is_err());
+        assert!(import("# room 0 a 0 0 2 1 - -\nE\n").is_err());
+        assert!(import("# room 0 a 5 0 2147483647 1 - -\nE\n")
+            .unwrap_err()
+            .contains("invalid id or size"));
+        let far = "# origin -2147483648 0\n# room 0 a 2147483646 0 1 1 - -\nE\n";
+        assert!(import(far).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! Colours the viewer draws maps in.
+use crate::map_gen::RoomPlacement;
+use crate::scene::Scene;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// An RGB colour.
+pub type Color = (u8, u8, u8);
+
+/// What decides the colour of a room's empty tiles.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorMode {
+    /// Every empty tile is the theme's `empty` colour.
+    Tile,
+    /// Rooms step along the gradient in the order they are placed.
+    Order,
+    /// Rooms step along the gradient by the number of rooms between them and
+    /// the first room.
+    Depth,
+    /// Rooms from the same template share a colour, taken from `templates` or
+    /// else picked from the gradient by the template's name.
+    Template,
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tile" => Ok(Self::Tile),
+            "order" => Ok(Self::Order),
+            "depth" => Ok(Self::Depth),
+            "template" => Ok(Self::Template),
+            _ => Err(format!(
+                "unknown colour mode `{}`, expected tile, order, depth or template",
+                s
+            )),
+        }
+    }
+}
+
+/// Colours for every tile type and room, read from a built-in theme or a JSON or
+/// TOML file. Fields missing from a file are taken from the classic theme.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// Dirt tiles, and everything that is not covered by a room.
+    pub dirt: Color,
+    /// Empty tiles when colouring by tile type.
+    pub empty: Color,
+    pub mode: ColorMode,
+    /// Colours that rooms are spread along, evenly spaced and blended between.
+    pub gradient: Vec<Color>,
+    /// Number of steps it takes to go from one end of the gradient to the other
+    /// before starting over.
+    pub period: usize,
+    /// Colours of specific templates by name when colouring by template.
+    pub templates: HashMap<String, Color>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            dirt: (u8::MAX, u8::MAX, u8::MAX),
+            empty: (0, 0, 0),
+            mode: ColorMode::Order,
+            gradient: vec![(0, 128, 255), (255, 128, 0)],
+            period: 255,
+            templates: HashMap::new(),
+        }
+    }
+}
+
+impl Theme {
+    /// Names of the built-in themes.
+    pub const BUILT_IN: [&'static str; 4] = ["classic", "dark", "depth", "template"];
+
+    /// Looks up a built-in theme by name.
+    pub fn built_in(name: &str) -> Option<Self> {
+        let classic = Self::default();
+        match name {
+            "classic" => Some(classic),
+            "dark" => Some(Self {
+                dirt: (0, 0, 0),
+                empty: (u8::MAX, u8::MAX, u8::MAX),
+                ..classic
+            }),
+            "depth" => Some(Self {
+                dirt: (16, 12, 8),
+                mode: ColorMode::Depth,
+                gradient: vec![(255, 240, 160), (230, 90, 30), (120, 20, 60), (30, 10, 60)],
+                period: 48,
+                ..classic
+            }),
+            "template" => Some(Self {
+                dirt: (40, 40, 40),
+                mode: ColorMode::Template,
+                gradient: vec![(230, 60, 60), (240, 200, 40), (60, 200, 90), (60, 120, 230)],
+                period: 11,
+                ..classic
+            }),
+            _ => None,
+        }
+    }
+
+    /// Reads a theme from a file, as TOML if its extension is `toml` and as
+    /// JSON otherwise.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read theme `{}`: {}", path.display(), e))?;
+        let theme: Self = if path.extension().is_some_and(|e| e == "toml") {
+            toml::from_str(&text).map_err(|e| e.to_string())
+        } else {
+            serde_json::from_str(&text).map_err(|e| e.to_string())
+        }
+        .map_err(|e| format!("invalid theme `{}`: {}", path.display(), e))?;
+        if theme.gradient.is_empty() || theme.period == 0 {
+            return Err(format!(
+                "invalid theme `{}`: the gradient and period cannot be empty",
+                path.display()
+            ));
+        }
+        Ok(theme)
+    }
+
+    /// Colour of the empty tiles of `placement`, which is the `order`th room
+    /// added to `scene`. Its parents have to be in the scene already.
+    pub fn room_color(&self, scene: &Scene, placement: &RoomPlacement, order: usize) -> Color {
+        match self.mode {
+            ColorMode::Tile => self.empty,
+            ColorMode::Order => self.gradient_at(order),
+            ColorMode::Depth => {
+                let mut depth = 0;
+                let mut parent = placement.parent;
+                while let Some(p) = parent.and_then(|id| scene.placement(id)) {
+                    depth += 1;
+                    parent = p.parent;
+                }
+                self.gradient_at(depth)
+            }
+            ColorMode::Template => match self.templates.get(&placement.room.name) {
+                Some(color) => *color,
+                // FNV-1a, so that a template keeps its colour from run to run.
+                None => self.gradient_at(
+                    placement
+                        .room
+                        .name
+                        .bytes()
+                        .fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+                            (h ^ b as u64).wrapping_mul(0x100_0000_01b3)
+                        }) as usize,
+                ),
+            },
+        }
+    }
+
+    /// Colour `step` steps along the gradient.
+    fn gradient_at(&self, step: usize) -> Color {
+        let stops = &self.gradient;
+        if stops.len() == 1 {
+            return stops[0];
+        }
+        let t = (step % self.period) as f64 / self.period as f64 * (stops.len() - 1) as f64;
+        let (i, frac) = (t as usize, t.fract());
+        let (a, b) = (stops[i], stops[(i + 1).min(stops.len() - 1)]);
+        let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * frac).round() as u8;
+        (mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2))
+    }
+}
+
+/// Parses the name of a built-in theme or the path of a theme file.
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Self::built_in(s) {
+            Some(theme) => Ok(theme),
+            None if Path::new(s).is_file() => Self::load(Path::new(s)),
+            None => Err(format!(
+                "`{}` is neither a theme file nor one of {}",
+                s,
+                Self::BUILT_IN.join(", ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_gen::{Room, Tile::*};
+    use cgmath::Point2;
+
+    #[test]
+    fn gradient_steps() {
+        let theme = Theme {
+            gradient: vec![(0, 0, 0), (100, 200, 0), (100, 0, 200)],
+            period: 4,
+            ..Theme::default()
+        };
+        assert_eq!(theme.gradient_at(0), (0, 0, 0));
+        assert_eq!(theme.gradient_at(1), (50, 100, 0));
+        assert_eq!(theme.gradient_at(2), (100, 200, 0));
+        assert_eq!(theme.gradient_at(3), (100, 100, 100));
+        assert_eq!(theme.gradient_at(4), (0, 0, 0));
+    }
+
+    #[test]
+    fn color_by_depth() {
+        let room = Room::new("room", &[&[Empty]]);
+        let mut scene = Scene::default();
+        scene.add(room.place(Point2::new(0, 0), 0, None), (0, 0, 0));
+        scene.add(room.place(Point2::new(1, 0), 1, Some(0)), (0, 0, 0));
+        let child = room.place(Point2::new(2, 0), 2, Some(1));
+        let theme = Theme {
+            mode: ColorMode::Depth,
+            gradient: vec![(0, 0, 0), (40, 40, 40)],
+            period: 4,
+            ..Theme::default()
+        };
+        assert_eq!(theme.room_color(&scene, &child, 0), (20, 20, 20));
+    }
+
+    #[test]
+    fn parse_files() {
+        let json: Theme = serde_json::from_str(
+            r#"{"dirt": [1, 2, 3], "mode": "template", "templates": {"shaft": [9, 9, 9]}}"#,
+        )
+        .unwrap();
+        assert_eq!(json.dirt, (1, 2, 3));
+        assert_eq!(json.mode, ColorMode::Template);
+        assert_eq!(json.period, Theme::default().period);
+        let toml: Theme = toml::from_str(
+            "mode = \"depth\"\ngradient = [[0, 0, 0], [255, 255, 255]]\n[templates]\nshaft = [9, 9, 9]\n",
+        )
+        .unwrap();
+        assert_eq!(toml.mode, ColorMode::Depth);
+        assert_eq!(toml.templates["shaft"], (9, 9, 9));
+        assert!(
+            serde_json::from_str::<Theme>(r#"{"dirt": [1, 2, 3], "walls": [0, 0, 0]}"#).is_err()
+        );
+    }
+}
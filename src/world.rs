@@ -0,0 +1,259 @@
+//! An endless map, generated lazily in square chunks.
+//!
+//! Every chunk is generated from the world seed and its coordinates alone, in
+//! whatever order chunks are asked for. Chunks are coloured like a
+//! checkerboard. Rooms in even chunks, those whose coordinates add up to an even
+//! number, grow outwards from a room in the middle of the chunk and may poke a
+//! little way out of one of its sides, though not near its corners. Odd chunks
+//! are grown from the rooms of the four even chunks around them, which are
+//! generated first if need be, and keep within their own square. That way rooms
+//! cross chunk boundaries without ever overlapping, and the odd chunks join up
+//! the even ones, although nothing guarantees that every pair of neighbouring
+//! chunks is connected.
+use crate::map_gen::{MapGenerator, RoomPlacement};
+use crate::rect::Rect;
+use cgmath::{EuclideanSpace, Point2, Vector2};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+
+/// Where the rooms of a chunk may be placed.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Region {
+    /// The chunk's own tiles.
+    pub(crate) square: Rect,
+    /// Tiles rooms may poke out of one side of the square by.
+    pub(crate) margin: i32,
+}
+
+impl Region {
+    /// Determines if a room covering `r` may be placed in the region. Rooms
+    /// crossing a side of the square have to stay `margin` tiles away from the
+    /// ends of that side, so that they never meet rooms crossing another side
+    /// from a different chunk.
+    pub(crate) fn allows(&self, r: &Rect) -> bool {
+        let (s, m) = (&self.square, self.margin);
+        let crosses_x = r.min.x < s.min.x || r.max.x > s.max.x;
+        let crosses_y = r.min.y < s.min.y || r.max.y > s.max.y;
+        match (crosses_x, crosses_y) {
+            (false, false) => true,
+            (true, false) => {
+                r.min.x >= s.min.x - m
+                    && r.max.x <= s.max.x + m
+                    && r.min.y >= s.min.y + m
+                    && r.max.y <= s.max.y - m
+            }
+            (false, true) => {
+                r.min.y >= s.min.y - m
+                    && r.max.y <= s.max.y + m
+                    && r.min.x >= s.min.x + m
+                    && r.max.x <= s.max.x - m
+            }
+            (true, true) => false,
+        }
+    }
+}
+
+/// The rooms generated for one chunk.
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    pub coords: Point2<i32>,
+    /// Rooms whose placement belongs to this chunk, although they may stick out
+    /// of it. Ids and parents refer to rooms of the same chunk, and rooms
+    /// attached to a room of another chunk have no parent.
+    pub rooms: Vec<RoomPlacement>,
+}
+
+/// A map without edges, made of chunks that are generated as they are needed.
+#[derive(Debug)]
+pub struct World {
+    seed: u64,
+    chunk_size: u32,
+    chunks: HashMap<Point2<i32>, Chunk>,
+}
+
+impl World {
+    /// Creates a world whose chunks are `chunk_size` tiles on a side.
+    pub fn new(seed: u64, chunk_size: u32) -> Self {
+        assert!(chunk_size >= 32, "chunks must fit several rooms");
+        Self {
+            seed,
+            chunk_size,
+            chunks: HashMap::new(),
+        }
+    }
+
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size
+    }
+
+    /// Coordinates of the chunk the tile at `pos` lies in.
+    pub fn chunk_at(&self, pos: Point2<i32>) -> Point2<i32> {
+        let size = self.chunk_size as i32;
+        Point2::new(pos.x.div_euclid(size), pos.y.div_euclid(size))
+    }
+
+    /// The tiles of the chunk at `coords`, not counting rooms poking out of
+    /// neighbouring chunks.
+    pub fn chunk_bounds(&self, coords: Point2<i32>) -> Rect {
+        let size = self.chunk_size as i32;
+        let min = Point2::new(coords.x * size, coords.y * size);
+        Rect {
+            min,
+            max: min + Vector2::new(size, size),
+        }
+    }
+
+    /// The chunk at `coords`, generating it first if need be.
+    pub fn chunk(&mut self, coords: Point2<i32>) -> &Chunk {
+        if !self.chunks.contains_key(&coords) {
+            let chunk = self.generate(coords);
+            self.chunks.insert(coords, chunk);
+        }
+        &self.chunks[&coords]
+    }
+
+    /// Generates every chunk up to `radius` chunks away from the one the tile at
+    /// `focus` lies in. Returns the coordinates of the chunks that are new,
+    /// nearest first.
+    pub fn generate_around(&mut self, focus: Point2<i32>, radius: u32) -> Vec<Point2<i32>> {
+        let center = self.chunk_at(focus);
+        let radius = radius as i32;
+        let mut around: Vec<Point2<i32>> = (-radius..=radius)
+            .flat_map(|y| (-radius..=radius).map(move |x| center + Vector2::new(x, y)))
+            .collect();
+        around.sort_by_key(|c| (c.x - center.x).abs().max((c.y - center.y).abs()));
+        around
+            .into_iter()
+            .filter(|coords| {
+                let new = !self.chunks.contains_key(coords);
+                self.chunk(*coords);
+                new
+            })
+            .collect()
+    }
+
+    /// Forgets every chunk more than `radius` chunks away from the one the tile
+    /// at `focus` lies in. They are generated the same way again if needed.
+    pub fn unload_beyond(&mut self, focus: Point2<i32>, radius: u32) {
+        let center = self.chunk_at(focus);
+        let radius = radius as i32;
+        self.chunks
+            .retain(|c, _| (c.x - center.x).abs() <= radius && (c.y - center.y).abs() <= radius);
+    }
+
+    /// Every chunk generated so far, in no particular order.
+    pub fn chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.values()
+    }
+
+    fn generate(&mut self, coords: Point2<i32>) -> Chunk {
+        let size = self.chunk_size;
+        let square = self.chunk_bounds(coords);
+        let rng = StdRng::seed_from_u64(chunk_seed(self.seed, coords));
+        let map_gen = MapGenerator::new(size, size, rng);
+        let (mut map_gen, neighbours) = if (coords.x + coords.y) % 2 == 0 {
+            // Move the first room into the middle of the chunk.
+            let mut start = map_gen.placements()[0].clone();
+            start.pos += square.min.to_vec();
+            let region = Region {
+                square,
+                margin: size as i32 / 4,
+            };
+            (map_gen.with_region(region).with_rooms(vec![start]), 0)
+        } else {
+            let neighbours: Vec<RoomPlacement> = [(0, -1), (1, 0), (0, 1), (-1, 0)]
+                .iter()
+                .flat_map(|(x, y)| self.chunk(coords + Vector2::new(*x, *y)).rooms.clone())
+                // Only rooms on the edge of the chunk or poking into it are in
+                // the way or can have rooms in the chunk attached to them.
+                .filter(|p| p.rect().padded(1).overlaps(&square))
+                .collect();
+            let count = neighbours.len();
+            let region = Region { square, margin: 0 };
+            (map_gen.with_region(region).with_rooms(neighbours), count)
+        };
+        map_gen.by_ref().for_each(drop);
+        let rooms = map_gen.placements()[neighbours..]
+            .iter()
+            .map(|placement| {
+                let mut room = placement.clone();
+                room.id -= neighbours;
+                room.parent = room
+                    .parent
+                    .filter(|parent| *parent >= neighbours)
+                    .map(|parent| parent - neighbours);
+                room
+            })
+            .collect();
+        Chunk { coords, rooms }
+    }
+}
+
+/// Seed of the chunk at `coords`, mixing the world seed with the coordinates
+/// so that neighbouring chunks get unrelated seeds.
+fn chunk_seed(seed: u64, coords: Point2<i32>) -> u64 {
+    // The finaliser of SplitMix64.
+    let mut z = seed ^ ((coords.x as u32 as u64) << 32 | coords.y as u32 as u64);
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Where each room of the chunk is and what it is.
+    fn layout(chunk: &Chunk) -> Vec<(Point2<i32>, String)> {
+        chunk
+            .rooms
+            .iter()
+            .map(|p| (p.pos, p.room.name.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn chunks_do_not_depend_on_order() {
+        let mut a = World::new(7, 48);
+        let mut b = World::new(7, 48);
+        b.generate_around(Point2::new(200, -100), 0);
+        b.generate_around(Point2::new(0, 0), 1);
+        for coords in [Point2::new(0, 0), Point2::new(1, 0), Point2::new(-1, -1)] {
+            let expected = layout(a.chunk(coords));
+            assert!(!expected.is_empty());
+            assert_eq!(layout(b.chunk(coords)), expected);
+        }
+        b.unload_beyond(Point2::new(0, 0), 0);
+        assert_eq!(b.chunks().count(), 1);
+        assert_eq!(
+            layout(b.chunk(Point2::new(1, 1))),
+            layout(a.chunk(Point2::new(1, 1)))
+        );
+    }
+
+    #[test]
+    fn rooms_cross_chunks_without_overlapping() {
+        let mut world = World::new(3, 48);
+        world.generate_around(Point2::new(0, 0), 1);
+        assert!(world.chunks().all(|c| !c.rooms.is_empty()));
+        let rooms: Vec<(Point2<i32>, Rect)> = world
+            .chunks()
+            .flat_map(|c| c.rooms.iter().map(move |p| (c.coords, p.rect())))
+            .collect();
+        for (i, (_, a)) in rooms.iter().enumerate() {
+            for (_, b) in &rooms[i + 1..] {
+                assert!(!a.overlaps(b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+        let crossing = rooms.iter().filter(|(coords, r)| {
+            let bounds = world.chunk_bounds(*coords);
+            r.min.x < bounds.min.x
+                || r.min.y < bounds.min.y
+                || r.max.x > bounds.max.x
+                || r.max.y > bounds.max.y
+        });
+        assert!(crossing.count() > 0);
+    }
+}